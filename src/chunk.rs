@@ -0,0 +1,181 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::{token::Span, Identifier, LoxNumber};
+
+/// The instructions of the [`Vm`](crate::vm::Vm). Each opcode is one
+/// byte, followed by its operands: constant indices and jump offsets are
+/// two bytes, big-endian, and slot indices and argument counts are one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// `constant: u16`. Pushes a number or string constant.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// `slot: u8`.
+    GetLocal,
+    /// `slot: u8`. Leaves the value on the stack.
+    SetLocal,
+    /// `name: u16`.
+    GetGlobal,
+    /// `name: u16`. Pops the value.
+    DefineGlobal,
+    /// `name: u16`. Leaves the value on the stack.
+    SetGlobal,
+    /// `index: u8`.
+    GetUpvalue,
+    /// `index: u8`. Leaves the value on the stack.
+    SetUpvalue,
+    /// `name: u16`. Replaces the instance with the property.
+    GetProperty,
+    /// `name: u16`. Pops the instance and the value, then pushes the value.
+    SetProperty,
+    /// `name: u16`. Pops the superclass and `self`, then pushes the bound method.
+    GetSuper,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    /// `offset: u16`, forwards.
+    Jump,
+    /// `offset: u16`, forwards. Leaves the condition on the stack.
+    JumpIfFalse,
+    /// `offset: u16`, backwards.
+    Loop,
+    /// `arguments: u8`. The callee is below the arguments.
+    Call,
+    /// `function: u16`, then `is_local: u8, index: u8` for each upvalue.
+    Closure,
+    /// Moves the local on top of the stack into the closures that captured it, and pops it.
+    CloseUpvalue,
+    Return,
+    /// `name: u16`. Pushes a new class.
+    Class,
+    /// `name: u16, superclass: u16`. Pops the superclass, then pushes the
+    /// new class and the superclass again, for methods to capture as `super`.
+    Subclass,
+    /// `name: u16`. Pops a closure and adds it to the class below it.
+    Method,
+}
+
+impl OpCode {
+    const ALL: &'static [OpCode] = &[
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Subclass,
+        OpCode::Method,
+    ];
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        OpCode::ALL.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+/// A value known at compile time.
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Number(LoxNumber),
+    String(Rc<str>),
+    /// The name of a global, a property or a class.
+    Name(Identifier),
+    Function(Rc<FunctionProto>),
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constant::Number(n) => write!(f, "{n}"),
+            Constant::String(s) => write!(f, "{s:?}"),
+            Constant::Name(name) => write!(f, "{name:?}"),
+            Constant::Function(proto) => write!(f, "<fn {}>", proto.name),
+        }
+    }
+}
+
+/// How deeply function declarations can be nested in one another. The
+/// compiler reports deeper nesting, and `.loxc` files that have it are
+/// rejected before they can exhaust the stack of the code reading them.
+pub const MAX_NESTING: usize = 256;
+
+/// A compiled function, before it captures any variables.
+#[derive(Debug)]
+pub struct FunctionProto {
+    pub name: Identifier,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+/// A sequence of instructions with the constants they refer to.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// The source each byte of `code` was compiled from, for error messages.
+    pub spans: Vec<Span>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, span: &Span) {
+        self.code.push(byte);
+        self.spans.push(span.clone());
+    }
+
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
@@ -0,0 +1,212 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    error::RuntimeError,
+    expr::eval::Value,
+    function::Function,
+    gc::{self, Trace, Tracer},
+    Identifier,
+};
+
+pub struct Class {
+    pub name: Identifier,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<Identifier, Rc<Function>>,
+}
+
+impl Class {
+    pub fn new(
+        name: Identifier,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<Identifier, Rc<Function>>,
+    ) -> Self {
+        Self { name, superclass, methods }
+    }
+
+    /// Looks up a method in this class, then up the superclass chain.
+    pub fn find_method(&self, name: Identifier) -> Option<&Rc<Function>> {
+        self.methods.get(&name).or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+
+    /// Calling a class takes as many arguments as its initializer.
+    pub fn arity(&self) -> usize {
+        self.find_method("init".into()).map_or(0, |init| init.arity())
+    }
+}
+
+/// Creates a new instance of `class` and runs its initializer, if any.
+pub fn instantiate(class: &Rc<Class>, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let instance = Instance::new(Rc::clone(class));
+    if let Some(init) = class.find_method("init".into()) {
+        init.bind(instance.clone()).call(args)?;
+    }
+    Ok(Value::Instance(instance))
+}
+
+impl Trace for Class {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(superclass) = &self.superclass {
+            tracer.edge(superclass);
+        }
+        self.methods.values().for_each(|method| tracer.edge(method));
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+/// A handle to an instance. Instances are shared, so setting a field
+/// through one handle is visible through all the others.
+#[derive(Clone)]
+pub struct Instance(Rc<RefCell<Fields>>);
+
+struct Fields {
+    class: Rc<Class>,
+    table: HashMap<Identifier, Value>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self(gc::alloc(RefCell::new(Fields {
+            class,
+            table: Default::default(),
+        })))
+    }
+
+    /// Reports the instance this handle refers to.
+    pub fn trace(&self, tracer: &mut Tracer) {
+        tracer.edge(&self.0);
+    }
+
+    /// Looks up a field, falling back to a method of the class bound to this instance.
+    pub fn get(&self, name: Identifier) -> Option<Value> {
+        let method = {
+            let fields = self.0.borrow();
+            if let Some(value) = fields.table.get(&name) {
+                return Some(value.clone());
+            }
+            Rc::clone(fields.class.find_method(name)?)
+        };
+        // Binding allocates, so the fields mustn't be borrowed any more.
+        Some(Value::Function(gc::alloc(method.bind(self.clone()))))
+    }
+
+    pub fn set(&self, name: Identifier, value: Value) {
+        self.0.borrow_mut().table.insert(name, value);
+    }
+}
+
+impl Trace for RefCell<Fields> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let fields = self.try_borrow().expect(gc::BORROWED);
+        tracer.edge(&fields.class);
+        fields.table.values().for_each(|value| value.trace(tracer));
+    }
+
+    fn clear(&self) {
+        self.try_borrow_mut().expect(gc::BORROWED).table.clear();
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<instance {}>", self.0.borrow().class.name)
+    }
+}
+
+#[test]
+fn initializers_always_return_the_instance() {
+    use crate::statement::eval::run_source;
+
+    let (env, result) = run_source("
+        class Point {
+            init(x) {
+                self.x = x;
+                if (x > 0) return;
+                self.x = 0;
+            }
+            sum(y) { return self.x + y; }
+        }
+        let p = Point(2);
+        let q = Point(-2);
+        let again = p.init(5);
+        let same = again == p;
+        let x = p.x;
+        let sum = q.sum(3);
+        let shown = p;
+    ");
+
+    assert!(result.unwrap().is_continue());
+    assert_eq!(env.global("same").to_string(), "true");
+    assert_eq!(env.global("x").to_string(), "5");
+    assert_eq!(env.global("sum").to_string(), "3");
+    assert_eq!(env.global("shown").to_string(), "<instance Point>");
+}
+
+#[test]
+fn initializers_cant_return_a_value() {
+    use crate::{code::Code, parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    let source = "class A { init() { return 1; } }";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    let errors = Resolver::default().resolve(&program).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, Code::ReturnFromInitializer);
+    assert_eq!(errors[0].span.offset(), source.find("1;").unwrap());
+}
+
+#[test]
+fn methods_are_inherited_and_super_binds_self() {
+    use crate::statement::eval::run_source;
+
+    let (env, result) = run_source("
+        class A {
+            name() { return \"A\"; }
+            describe() { return \"I am \" + self.name(); }
+        }
+        class B < A {
+            name() { return \"B\" + super.name(); }
+        }
+        class C < B {
+            init() { self.tag = \"c\"; }
+            describe() { return super.describe() + \" tagged \" + self.tag; }
+        }
+        let inherited = C().name();
+        let described = C().describe();
+    ");
+
+    assert!(result.unwrap().is_continue());
+    // `C` finds `name` on `B`, whose `super` is `A` even when called on a `C`.
+    assert_eq!(env.global("inherited").to_string(), "BA");
+    // `super.describe()` still calls `name` on the `C` instance.
+    assert_eq!(env.global("described").to_string(), "I am BA tagged c");
+}
+
+#[test]
+fn inheriting_from_a_non_class_is_a_runtime_error() {
+    use crate::{code::Code, statement::eval::run_source};
+
+    let source = "let NotAClass = 1; class Sub < NotAClass {}";
+    let Err(err) = run_source(source).1 else {
+        panic!("expected an invalid superclass");
+    };
+
+    assert_eq!(err.code(), Code::InvalidSuperclass);
+    assert_eq!(err.to_string(), "InvalidSuperclass: NotAClass is not a class");
+    assert_eq!(err.span().offset(), source.rfind("NotAClass").unwrap());
+}
@@ -0,0 +1,173 @@
+use std::{fmt::Display, str::FromStr};
+
+/// A stable identifier for a kind of diagnostic. Codes never change
+/// meaning once published, so docs and tools can refer to them instead
+/// of to message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    // Scanner errors.
+    UnexpectedCharacter,
+    UnterminatedString,
+    // Syntax errors.
+    ExpectedExpression,
+    ExpectedToken,
+    InvalidAssignmentTarget,
+    TooManyArguments,
+    /// No longer reported, since `let x;` binds `nil`. Kept so the code isn't reused.
+    MissingInitializer,
+    // Scope errors.
+    ReadInOwnInitializer,
+    AlreadyDeclared,
+    TopLevelReturn,
+    ReturnFromInitializer,
+    SelfOutsideClass,
+    SuperOutsideClass,
+    SuperWithoutSuperclass,
+    InheritsFromItself,
+    // Compile errors.
+    BytecodeLimit,
+    InvalidBytecodeFile,
+    // Runtime errors.
+    UnboundVariable,
+    TypeMismatch,
+    ArityMismatch,
+    NotCallable,
+    NotAnInstance,
+    UndefinedProperty,
+    InvalidSuperclass,
+    StackOverflow,
+    // Warnings.
+    UnusedBinding,
+    ShadowedBinding,
+    UnreachableCode,
+    ConstantCondition,
+}
+
+impl Code {
+    pub const ALL: &'static [Code] = &[
+        Code::UnexpectedCharacter,
+        Code::UnterminatedString,
+        Code::ExpectedExpression,
+        Code::ExpectedToken,
+        Code::InvalidAssignmentTarget,
+        Code::TooManyArguments,
+        Code::MissingInitializer,
+        Code::ReadInOwnInitializer,
+        Code::AlreadyDeclared,
+        Code::TopLevelReturn,
+        Code::ReturnFromInitializer,
+        Code::SelfOutsideClass,
+        Code::SuperOutsideClass,
+        Code::SuperWithoutSuperclass,
+        Code::InheritsFromItself,
+        Code::BytecodeLimit,
+        Code::InvalidBytecodeFile,
+        Code::UnboundVariable,
+        Code::TypeMismatch,
+        Code::ArityMismatch,
+        Code::NotCallable,
+        Code::NotAnInstance,
+        Code::UndefinedProperty,
+        Code::InvalidSuperclass,
+        Code::StackOverflow,
+        Code::UnusedBinding,
+        Code::ShadowedBinding,
+        Code::UnreachableCode,
+        Code::ConstantCondition,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => "E0001",
+            Code::UnterminatedString => "E0002",
+            Code::ExpectedExpression => "E0100",
+            Code::ExpectedToken => "E0101",
+            Code::InvalidAssignmentTarget => "E0102",
+            Code::TooManyArguments => "E0103",
+            Code::MissingInitializer => "E0104",
+            Code::ReadInOwnInitializer => "E0200",
+            Code::AlreadyDeclared => "E0201",
+            Code::TopLevelReturn => "E0202",
+            Code::ReturnFromInitializer => "E0203",
+            Code::SelfOutsideClass => "E0204",
+            Code::SuperOutsideClass => "E0205",
+            Code::SuperWithoutSuperclass => "E0206",
+            Code::InheritsFromItself => "E0207",
+            Code::BytecodeLimit => "E0400",
+            Code::InvalidBytecodeFile => "E0401",
+            Code::UnboundVariable => "E0300",
+            Code::TypeMismatch => "E0301",
+            Code::ArityMismatch => "E0302",
+            Code::NotCallable => "E0303",
+            Code::NotAnInstance => "E0304",
+            Code::UndefinedProperty => "E0305",
+            Code::InvalidSuperclass => "E0306",
+            Code::StackOverflow => "E0307",
+            Code::UnusedBinding => "W0001",
+            Code::ShadowedBinding => "W0002",
+            Code::UnreachableCode => "W0003",
+            Code::ConstantCondition => "W0004",
+        }
+    }
+
+    /// The long-form explanation shown by `rlox --explain`.
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => include_str!("code/E0001.md"),
+            Code::UnterminatedString => include_str!("code/E0002.md"),
+            Code::ExpectedExpression => include_str!("code/E0100.md"),
+            Code::ExpectedToken => include_str!("code/E0101.md"),
+            Code::InvalidAssignmentTarget => include_str!("code/E0102.md"),
+            Code::TooManyArguments => include_str!("code/E0103.md"),
+            Code::MissingInitializer => include_str!("code/E0104.md"),
+            Code::ReadInOwnInitializer => include_str!("code/E0200.md"),
+            Code::AlreadyDeclared => include_str!("code/E0201.md"),
+            Code::TopLevelReturn => include_str!("code/E0202.md"),
+            Code::ReturnFromInitializer => include_str!("code/E0203.md"),
+            Code::SelfOutsideClass => include_str!("code/E0204.md"),
+            Code::SuperOutsideClass => include_str!("code/E0205.md"),
+            Code::SuperWithoutSuperclass => include_str!("code/E0206.md"),
+            Code::InheritsFromItself => include_str!("code/E0207.md"),
+            Code::BytecodeLimit => include_str!("code/E0400.md"),
+            Code::InvalidBytecodeFile => include_str!("code/E0401.md"),
+            Code::UnboundVariable => include_str!("code/E0300.md"),
+            Code::TypeMismatch => include_str!("code/E0301.md"),
+            Code::ArityMismatch => include_str!("code/E0302.md"),
+            Code::NotCallable => include_str!("code/E0303.md"),
+            Code::NotAnInstance => include_str!("code/E0304.md"),
+            Code::UndefinedProperty => include_str!("code/E0305.md"),
+            Code::InvalidSuperclass => include_str!("code/E0306.md"),
+            Code::StackOverflow => include_str!("code/E0307.md"),
+            Code::UnusedBinding => include_str!("code/W0001.md"),
+            Code::ShadowedBinding => include_str!("code/W0002.md"),
+            Code::UnreachableCode => include_str!("code/W0003.md"),
+            Code::ConstantCondition => include_str!("code/W0004.md"),
+        }
+    }
+}
+
+impl FromStr for Code {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Code::ALL
+            .iter()
+            .find(|code| code.as_str().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(())
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[test]
+fn codes_are_unique_and_round_trip() {
+    for (i, code) in Code::ALL.iter().enumerate() {
+        assert_eq!(code.as_str().parse(), Ok(*code));
+        assert!(Code::ALL[..i].iter().all(|other| other.as_str() != code.as_str()));
+    }
+}
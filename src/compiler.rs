@@ -0,0 +1,520 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    chunk::{Constant, FunctionProto, OpCode, MAX_NESTING},
+    code::Code,
+    error::CompileError,
+    expr::{BinaryOp, Expr, ExprKind, Literal, LogicalOp, UnaryOp},
+    statement::{FunctionDecl, Statement, StatementKind},
+    token::Span,
+    Identifier,
+};
+
+/// Lowers a resolved program to bytecode for the [`Vm`](crate::vm::Vm).
+///
+/// Each top-level statement becomes its own script function, so that the
+/// VM can go on with the next one after a runtime error, like the
+/// tree-walker does. Locals live on the VM stack, and locals captured by
+/// closures are reached through upvalues.
+#[derive(Default)]
+pub struct Compiler {
+    /// The function being compiled is last; the ones it is nested in come before it.
+    functions: Vec<FunctionState>,
+    errors: Vec<CompileError>,
+}
+
+struct FunctionState {
+    proto: FunctionProto,
+    kind: FunctionKind,
+    /// Indexed by stack slot, relative to the start of the call frame.
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+    /// Constants holding names, so each name is stored once per chunk.
+    names: HashMap<Identifier, u16>,
+}
+
+struct Local {
+    name: Identifier,
+    depth: usize,
+    is_captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Upvalue {
+    /// Whether this captures a local of the enclosing function, or one of its upvalues.
+    is_local: bool,
+    index: u8,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+impl Compiler {
+    /// Returns one script function per top-level statement, in order.
+    pub fn compile(mut self, program: &[Statement]) -> Result<Vec<Rc<FunctionProto>>, Vec<CompileError>> {
+        let mut scripts = Vec::new();
+        for stmt in program {
+            self.begin_function("script".into(), FunctionKind::Script);
+            self.statement(stmt);
+            let (proto, _) = self.end_function(&stmt.span);
+            scripts.push(Rc::new(proto));
+        }
+
+        if self.errors.is_empty() {
+            Ok(scripts)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        let span = &stmt.span;
+        match &stmt.node {
+            StatementKind::Block(stmts) => {
+                self.begin_scope();
+                for stmt in stmts {
+                    self.statement(stmt);
+                }
+                self.end_scope(span);
+            }
+            StatementKind::Class(decl) => {
+                let name = self.identifier_constant(decl.name.node, &decl.name.span);
+                // The class stays in this slot while its methods are added. It's
+                // the variable itself for a local class, and a temporary otherwise.
+                let class_slot = self.current().locals.len();
+                let is_global = self.current().scope_depth == 0;
+
+                match &decl.superclass {
+                    Some(superclass) => {
+                        self.get_variable(superclass.name, &superclass.span);
+                        let superclass_name = self.identifier_constant(superclass.name, &superclass.span);
+                        self.emit_op(OpCode::Subclass, &superclass.span);
+                        self.emit_u16(name, &superclass.span);
+                        self.emit_u16(superclass_name, &superclass.span);
+                    }
+                    None => {
+                        self.emit_op(OpCode::Class, &decl.name.span);
+                        self.emit_u16(name, &decl.name.span);
+                    }
+                }
+                let class_name = if is_global { "".into() } else { decl.name.node };
+                self.add_local(class_name, &decl.name.span);
+
+                if decl.superclass.is_some() {
+                    self.begin_scope();
+                    self.add_local("super".into(), &decl.name.span);
+                }
+
+                self.emit_op(OpCode::GetLocal, &decl.name.span);
+                self.emit_slot(class_slot, &decl.name.span);
+                for method in &decl.methods {
+                    let kind = if *method.name == "init" { FunctionKind::Initializer } else { FunctionKind::Method };
+                    self.function(method, kind);
+                    let method_name = self.identifier_constant(method.name.node, &method.name.span);
+                    self.emit_op(OpCode::Method, &method.name.span);
+                    self.emit_u16(method_name, &method.name.span);
+                }
+                self.emit_op(OpCode::Pop, span);
+
+                if decl.superclass.is_some() {
+                    self.end_scope(span);
+                }
+                if is_global {
+                    self.current().locals.pop();
+                    self.emit_op(OpCode::DefineGlobal, &decl.name.span);
+                    self.emit_u16(name, &decl.name.span);
+                }
+            }
+            StatementKind::Expr(expr) => {
+                self.expr(expr);
+                self.emit_op(OpCode::Pop, span);
+            }
+            StatementKind::Fn(decl) => {
+                // A local function is in scope in its own body, so it can recurse.
+                if self.current().scope_depth > 0 {
+                    self.add_local(decl.name.node, &decl.name.span);
+                }
+                self.function(decl, FunctionKind::Function);
+                self.define_variable(decl.name.node, &decl.name.span);
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+                self.emit_op(OpCode::Pop, span);
+                self.statement(then_branch);
+
+                let else_jump = self.emit_jump(OpCode::Jump, span);
+                self.patch_jump(then_jump, span);
+                self.emit_op(OpCode::Pop, span);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump, span);
+            }
+            StatementKind::Let(name, expr) => {
+                match expr {
+                    Some(expr) => self.expr(expr),
+                    None => self.emit_op(OpCode::Nil, span),
+                }
+                if self.current().scope_depth > 0 {
+                    self.add_local(name.node, &name.span);
+                }
+                self.define_variable(name.node, &name.span);
+            }
+            StatementKind::Print(expr) => {
+                self.expr(expr);
+                self.emit_op(OpCode::Print, span);
+            }
+            StatementKind::Return(expr) => {
+                match expr {
+                    Some(expr) => self.expr(expr),
+                    None => self.emit_default_return_value(span),
+                }
+                self.emit_op(OpCode::Return, span);
+            }
+            StatementKind::While(condition, body) => {
+                let loop_start = self.current().proto.chunk.code.len();
+                self.expr(condition);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+                self.emit_op(OpCode::Pop, span);
+                self.statement(body);
+                self.emit_loop(loop_start, span);
+
+                self.patch_jump(exit_jump, span);
+                self.emit_op(OpCode::Pop, span);
+            }
+        }
+    }
+
+    /// Compiles `decl` into a constant and emits the instruction that
+    /// turns it into a closure at runtime.
+    fn function(&mut self, decl: &FunctionDecl, kind: FunctionKind) {
+        let span = &decl.name.span;
+        // The script itself isn't nested in anything.
+        if self.functions.len() > MAX_NESTING {
+            self.error("Too many nested functions.", span);
+        }
+        self.begin_function(decl.name.node, kind);
+        self.current().proto.arity = decl.params.len();
+        for param in &decl.params {
+            self.add_local(param.node, &param.span);
+        }
+        for stmt in &decl.body {
+            self.statement(stmt);
+        }
+        let (proto, upvalues) = self.end_function(span);
+
+        let constant = self.make_constant(Constant::Function(Rc::new(proto)), span);
+        self.emit_op(OpCode::Closure, span);
+        self.emit_u16(constant, span);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8, span);
+            self.emit_byte(upvalue.index, span);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let span = &expr.span;
+        match &expr.node {
+            ExprKind::Assign(variable, value) => {
+                self.expr(value);
+                self.set_variable(variable.name, &variable.span);
+            }
+            ExprKind::Binary(op, left, right) => {
+                self.expr(left);
+                self.expr(right);
+                let op = match op {
+                    BinaryOp::Minus => OpCode::Subtract,
+                    BinaryOp::Plus => OpCode::Add,
+                    BinaryOp::Div => OpCode::Divide,
+                    BinaryOp::Mul => OpCode::Multiply,
+                    BinaryOp::NotEqual => OpCode::NotEqual,
+                    BinaryOp::Equal => OpCode::Equal,
+                    BinaryOp::Greater => OpCode::Greater,
+                    BinaryOp::GreaterEqual => OpCode::GreaterEqual,
+                    BinaryOp::Less => OpCode::Less,
+                    BinaryOp::LessEqual => OpCode::LessEqual,
+                };
+                self.emit_op(op, span);
+            }
+            ExprKind::Call(callee, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+                self.emit_op(OpCode::Call, span);
+                // The parser doesn't allow more than 255 arguments.
+                self.emit_byte(args.len() as u8, span);
+            }
+            ExprKind::Get(object, name) => {
+                self.expr(object);
+                let name_constant = self.identifier_constant(name.node, &name.span);
+                self.emit_op(OpCode::GetProperty, &name.span);
+                self.emit_u16(name_constant, &name.span);
+            }
+            ExprKind::Grouping(expr) => self.expr(expr),
+            ExprKind::Literal(literal) => match literal {
+                Literal::Boolean(true) => self.emit_op(OpCode::True, span),
+                Literal::Boolean(false) => self.emit_op(OpCode::False, span),
+                Literal::Nil => self.emit_op(OpCode::Nil, span),
+                Literal::Number(n) => self.emit_constant(Constant::Number(*n), span),
+                Literal::String(s) => self.emit_constant(Constant::String(Rc::clone(s)), span),
+            },
+            ExprKind::Logical(op, left, right) => {
+                self.expr(left);
+                let end_jump = match op {
+                    LogicalOp::And => self.emit_jump(OpCode::JumpIfFalse, span),
+                    LogicalOp::Or => {
+                        let else_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+                        let end_jump = self.emit_jump(OpCode::Jump, span);
+                        self.patch_jump(else_jump, span);
+                        end_jump
+                    }
+                };
+                self.emit_op(OpCode::Pop, span);
+                self.expr(right);
+                self.patch_jump(end_jump, span);
+            }
+            ExprKind::Self_(variable) => self.get_variable(variable.name, &variable.span),
+            ExprKind::Set(object, name, value) => {
+                self.expr(object);
+                self.expr(value);
+                let name_constant = self.identifier_constant(name.node, &name.span);
+                self.emit_op(OpCode::SetProperty, &name.span);
+                self.emit_u16(name_constant, &name.span);
+            }
+            ExprKind::Super(variable, method) => {
+                self.get_variable("self".into(), &variable.span);
+                self.get_variable(variable.name, &variable.span);
+                let name_constant = self.identifier_constant(method.node, &method.span);
+                self.emit_op(OpCode::GetSuper, &method.span);
+                self.emit_u16(name_constant, &method.span);
+            }
+            ExprKind::Unary(op, operand) => {
+                self.expr(operand);
+                match op {
+                    UnaryOp::Minus => self.emit_op(OpCode::Negate, span),
+                    UnaryOp::Not => self.emit_op(OpCode::Not, span),
+                }
+            }
+            ExprKind::Variable(variable) => self.get_variable(variable.name, &variable.span),
+        }
+    }
+
+    fn get_variable(&mut self, name: Identifier, span: &Span) {
+        let depth = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(depth, name) {
+            self.emit_op(OpCode::GetLocal, span);
+            self.emit_byte(slot, span);
+        } else if let Some(index) = self.resolve_upvalue(depth, name, span) {
+            self.emit_op(OpCode::GetUpvalue, span);
+            self.emit_byte(index, span);
+        } else {
+            let name = self.identifier_constant(name, span);
+            self.emit_op(OpCode::GetGlobal, span);
+            self.emit_u16(name, span);
+        }
+    }
+
+    fn set_variable(&mut self, name: Identifier, span: &Span) {
+        let depth = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(depth, name) {
+            self.emit_op(OpCode::SetLocal, span);
+            self.emit_byte(slot, span);
+        } else if let Some(index) = self.resolve_upvalue(depth, name, span) {
+            self.emit_op(OpCode::SetUpvalue, span);
+            self.emit_byte(index, span);
+        } else {
+            let name = self.identifier_constant(name, span);
+            self.emit_op(OpCode::SetGlobal, span);
+            self.emit_u16(name, span);
+        }
+    }
+
+    /// Binds the value on top of the stack to `name`. Locals are already
+    /// in their slot, so only globals need an instruction.
+    fn define_variable(&mut self, name: Identifier, span: &Span) {
+        if self.current().scope_depth == 0 {
+            let name = self.identifier_constant(name, span);
+            self.emit_op(OpCode::DefineGlobal, span);
+            self.emit_u16(name, span);
+        }
+    }
+
+    fn resolve_local(&self, function: usize, name: Identifier) -> Option<u8> {
+        let locals = &self.functions[function].locals;
+        // Slots never exceed `u8::MAX`, `add_local` reports it otherwise.
+        locals.iter().rposition(|local| local.name == name).map(|slot| slot as u8)
+    }
+
+    /// Finds `name` in the functions enclosing `function` and threads an
+    /// upvalue for it through every function in between.
+    fn resolve_upvalue(&mut self, function: usize, name: Identifier, span: &Span) -> Option<u8> {
+        if function == 0 {
+            return None;
+        }
+
+        if let Some(slot) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(function, Upvalue { is_local: true, index: slot }, span));
+        }
+
+        let index = self.resolve_upvalue(function - 1, name, span)?;
+        Some(self.add_upvalue(function, Upvalue { is_local: false, index }, span))
+    }
+
+    fn add_upvalue(&mut self, function: usize, upvalue: Upvalue, span: &Span) -> u8 {
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(index) = upvalues.iter().position(|existing| *existing == upvalue) {
+            return index as u8;
+        }
+        if upvalues.len() > u8::MAX as usize {
+            self.error("Too many closure variables in function.", span);
+            return 0;
+        }
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u8
+    }
+
+    fn add_local(&mut self, name: Identifier, span: &Span) {
+        if self.current().locals.len() > u8::MAX as usize {
+            self.error("Too many local variables in function.", span);
+            return;
+        }
+        let depth = self.current().scope_depth;
+        self.current().locals.push(Local { name, depth, is_captured: false });
+    }
+
+    fn begin_function(&mut self, name: Identifier, kind: FunctionKind) {
+        // Slot zero holds the function being called, or `self` in methods.
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "self",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+        self.functions.push(FunctionState {
+            proto: FunctionProto { name, arity: 0, upvalue_count: 0, chunk: Default::default() },
+            kind,
+            locals: vec![Local { name: slot_zero.into(), depth: 0, is_captured: false }],
+            upvalues: Vec::new(),
+            scope_depth: if kind == FunctionKind::Script { 0 } else { 1 },
+            names: HashMap::new(),
+        });
+    }
+
+    fn end_function(&mut self, span: &Span) -> (FunctionProto, Vec<Upvalue>) {
+        self.emit_default_return_value(span);
+        self.emit_op(OpCode::Return, span);
+
+        let mut state = self.functions.pop().expect("a function is being compiled");
+        state.proto.upvalue_count = state.upvalues.len();
+        (state.proto, state.upvalues)
+    }
+
+    /// An initializer returns `self`, every other function `nil`.
+    fn emit_default_return_value(&mut self, span: &Span) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_op(OpCode::GetLocal, span);
+            self.emit_byte(0, span);
+        } else {
+            self.emit_op(OpCode::Nil, span);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, span: &Span) {
+        self.current().scope_depth -= 1;
+        let depth = self.current().scope_depth;
+        while let Some(local) = self.current().locals.pop_if(|local| local.depth > depth) {
+            let op = if local.is_captured { OpCode::CloseUpvalue } else { OpCode::Pop };
+            self.emit_op(op, span);
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().expect("a function is being compiled")
+    }
+
+    fn identifier_constant(&mut self, name: Identifier, span: &Span) -> u16 {
+        if let Some(&index) = self.current().names.get(&name) {
+            return index;
+        }
+        let index = self.make_constant(Constant::Name(name), span);
+        self.current().names.insert(name, index);
+        index
+    }
+
+    fn make_constant(&mut self, constant: Constant, span: &Span) -> u16 {
+        let index = self.current().proto.chunk.add_constant(constant);
+        u16::try_from(index).unwrap_or_else(|_| {
+            self.error("Too many constants in one chunk.", span);
+            0
+        })
+    }
+
+    fn emit_constant(&mut self, constant: Constant, span: &Span) {
+        let index = self.make_constant(constant, span);
+        self.emit_op(OpCode::Constant, span);
+        self.emit_u16(index, span);
+    }
+
+    /// Emits a jump with a placeholder offset, and returns where the offset is.
+    fn emit_jump(&mut self, op: OpCode, span: &Span) -> usize {
+        self.emit_op(op, span);
+        self.emit_u16(u16::MAX, span);
+        self.current().proto.chunk.code.len() - 2
+    }
+
+    /// Points the jump whose offset is at `offset` to the next instruction.
+    fn patch_jump(&mut self, offset: usize, span: &Span) {
+        let jump = self.current().proto.chunk.code.len() - offset - 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            return self.error("Too much code to jump over.", span);
+        };
+        self.current().proto.chunk.code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, span: &Span) {
+        self.emit_op(OpCode::Loop, span);
+        let jump = self.current().proto.chunk.code.len() - loop_start + 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            return self.error("Loop body too large.", span);
+        };
+        self.emit_u16(jump, span);
+    }
+
+    fn emit_op(&mut self, op: OpCode, span: &Span) {
+        self.emit_byte(op as u8, span);
+    }
+
+    fn emit_slot(&mut self, slot: usize, span: &Span) {
+        // Slots never exceed `u8::MAX`, `add_local` reports it otherwise.
+        self.emit_byte(slot as u8, span);
+    }
+
+    fn emit_u16(&mut self, value: u16, span: &Span) {
+        for byte in value.to_be_bytes() {
+            self.emit_byte(byte, span);
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8, span: &Span) {
+        self.current().proto.chunk.write(byte, span);
+    }
+
+    fn error(&mut self, message: &str, span: &Span) {
+        self.errors.push(CompileError {
+            code: Code::BytecodeLimit,
+            message: message.to_string(),
+            span: span.clone(),
+        });
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt::Write;
+
+use console::StyledObject;
+
+use crate::{
+    code::Code,
+    error::{CompileError, LoadError, ResolverError, RuntimeError, ScannerError, SyntaxError},
+    lint::Warning,
+    source_map::SourceMap,
+    token::Span,
+};
+
+/// Diagnostics are printed to stderr, so they are colored when it is a terminal.
+fn style<D>(value: D) -> StyledObject<D> {
+    console::style(value).for_stderr()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A message about the program, rendered compiler-style with the
+/// offending source line and a marker under the exact span.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<Code>,
+    pub message: String,
+    pub span: Option<Span>,
+    pub help: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span: None,
+            help: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    pub fn with_code(mut self, code: Code) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders the diagnostic as:
+    ///
+    /// ```text
+    /// error[E0100]: Expected expression.
+    ///  --> script.lox:2:11
+    ///   |
+    /// 2 | print 1 +;
+    ///   |          ^
+    ///   = help: ...
+    ///   = note: ...
+    /// ```
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let mut out = String::new();
+        let severity = match self.severity {
+            Severity::Error => style("error").red().bold(),
+            Severity::Warning => style("warning").yellow().bold(),
+        };
+        let code = match self.code {
+            Some(code) => format!("[{code}]"),
+            None => String::new(),
+        };
+        let code = match self.severity {
+            Severity::Error => style(code).red().bold(),
+            Severity::Warning => style(code).yellow().bold(),
+        };
+        // Writing into a `String` can't fail.
+        let _ = writeln!(out, "{severity}{code}{} {}", style(":").bold(), style(&self.message).bold());
+
+        let Some(span) = &self.span else {
+            let _ = writeln!(out, " {} {}", style("-->").blue().bold(), source_map.path());
+            return self.render_help(out, 1);
+        };
+
+        let location = source_map.location(span);
+        let text = source_map.line(location.line);
+        let gutter = location.line.to_string().len();
+        let bar = style("|").blue().bold();
+
+        let _ = writeln!(out, "{:gutter$}{} {location}", "", style("-->").blue().bold());
+        let _ = writeln!(out, "{:gutter$} {bar}", "");
+        let _ = writeln!(out, "{} {bar} {text}", style(location.line).blue().bold());
+
+        // Keep tabs so the marker lines up with the source, and don't
+        // underline past the end of the line for multi-line spans.
+        let indent: String = text
+            .chars()
+            .take(location.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = span.len().clamp(1, (text.len() + 1).saturating_sub(location.column).max(1));
+        let marker = match self.severity {
+            Severity::Error => style("^".repeat(width)).red().bold(),
+            Severity::Warning => style("^".repeat(width)).yellow().bold(),
+        };
+        let _ = writeln!(out, "{:gutter$} {bar} {indent}{marker}", "");
+
+        self.render_help(out, gutter)
+    }
+
+    /// Renders the diagnostic as a single-line JSON object, for tools:
+    ///
+    /// ```text
+    /// {"code":"E0100","severity":"error","message":"Expected expression.","file":"script.lox",
+    ///  "span":{"offset":20,"length":1},"line":2,"column":10,"help":null,"notes":[]}
+    /// ```
+    ///
+    /// `span`, `line` and `column` are `null` when there is no span.
+    pub fn render_json(&self, source_map: &SourceMap) -> String {
+        let code = self.code.map_or("null".to_string(), |code| json_string(code.as_str()));
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let (span, line, column) = match &self.span {
+            Some(span) => {
+                let location = source_map.location(span);
+                (
+                    format!(r#"{{"offset":{},"length":{}}}"#, span.offset(), span.len()),
+                    location.line.to_string(),
+                    location.column.to_string(),
+                )
+            }
+            None => ("null".to_string(), "null".to_string(), "null".to_string()),
+        };
+        let help = self.help.as_deref().map_or("null".to_string(), json_string);
+        let notes: Vec<_> = self.notes.iter().map(|note| json_string(note)).collect();
+
+        format!(
+            r#"{{"code":{code},"severity":"{severity}","message":{},"file":{},"span":{span},"line":{line},"column":{column},"help":{help},"notes":[{}]}}"#,
+            json_string(&self.message),
+            json_string(source_map.path()),
+            notes.join(","),
+        )
+    }
+
+    fn render_help(&self, mut out: String, gutter: usize) -> String {
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{:gutter$} {} {help}", "", style("= help:").bold());
+        }
+        for note in &self.notes {
+            let _ = writeln!(out, "{:gutter$} {} {note}", "", style("= note:").bold());
+        }
+        out
+    }
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl From<&ScannerError> for Diagnostic {
+    fn from(err: &ScannerError) -> Self {
+        Diagnostic::error(&err.message).with_code(err.code).with_span(err.span.clone())
+    }
+}
+
+impl From<&SyntaxError> for Diagnostic {
+    fn from(err: &SyntaxError) -> Self {
+        Diagnostic::error(&err.message).with_code(err.code).with_span(err.span.clone())
+    }
+}
+
+impl From<&ResolverError> for Diagnostic {
+    fn from(err: &ResolverError) -> Self {
+        Diagnostic::error(&err.message).with_code(err.code).with_span(err.span.clone())
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(err: &CompileError) -> Self {
+        Diagnostic::error(&err.message).with_code(err.code).with_span(err.span.clone())
+    }
+}
+
+impl From<&LoadError> for Diagnostic {
+    fn from(err: &LoadError) -> Self {
+        Diagnostic::error(&err.message).with_code(Code::InvalidBytecodeFile)
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        Diagnostic::warning(&warning.message).with_code(warning.code).with_span(warning.span.clone())
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(err: &RuntimeError) -> Self {
+        let diagnostic = Diagnostic::error(err.to_string())
+            .with_code(err.code())
+            .with_span(err.span().clone());
+
+        match err {
+            RuntimeError::UnboundVariable(_) => {
+                diagnostic.with_help("variables must be declared with `let` before they are used")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+#[test]
+fn renders_snippet_with_marker() {
+    console::set_colors_enabled(false);
+    let source_map = SourceMap::new("test.lox", "let a = 1;\nprint a +;\n");
+    let diagnostic = Diagnostic::error("Expected expression.")
+        .with_code(Code::ExpectedExpression)
+        .with_span(Span::new(20, 1))
+        .with_help("add an operand");
+
+    assert_eq!(
+        diagnostic.render(&source_map),
+        "error[E0100]: Expected expression.\n \
+         --> test.lox:2:10\n  \
+         |\n\
+         2 | print a +;\n  \
+         |          ^\n  \
+         = help: add an operand\n"
+    );
+}
+
+#[test]
+fn renders_one_json_object_per_diagnostic() {
+    let source_map = SourceMap::new("test.lox", "let a = 1;\nprint \"a\" +;\n");
+    let diagnostic = Diagnostic::error("Expected \"expression\".")
+        .with_code(Code::ExpectedExpression)
+        .with_span(Span::new(22, 1));
+
+    assert_eq!(
+        diagnostic.render_json(&source_map),
+        r#"{"code":"E0100","severity":"error","message":"Expected \"expression\".","file":"test.lox","span":{"offset":22,"length":1},"line":2,"column":12,"help":null,"notes":[]}"#
+    );
+}
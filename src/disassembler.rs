@@ -0,0 +1,149 @@
+use std::fmt::Write;
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+    source_map::SourceMap,
+};
+
+/// Prints bytecode made by the [`Compiler`](crate::compiler::Compiler) one
+/// instruction per line, with its offset, source line, opcode and operands.
+pub struct Disassembler<'a> {
+    source_map: &'a SourceMap,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(source_map: &'a SourceMap) -> Self {
+        Self { source_map }
+    }
+
+    /// Disassembles `function`, followed by every function nested in it.
+    pub fn function(&self, function: &FunctionProto) -> String {
+        let mut out = String::new();
+        self.write_function(&mut out, function);
+        out
+    }
+
+    fn write_function(&self, out: &mut String, function: &FunctionProto) {
+        let chunk = &function.chunk;
+        let _ = writeln!(out, "== {} ==", function.name);
+        let mut offset = 0;
+        while offset < chunk.code.len() {
+            let (text, next) = self.instruction(chunk, offset);
+            let _ = writeln!(out, "{text}");
+            offset = next;
+        }
+
+        for constant in &chunk.constants {
+            if let Constant::Function(nested) = constant {
+                out.push('\n');
+                self.write_function(out, nested);
+            }
+        }
+    }
+
+    /// Disassembles the instruction at `offset`, and returns it with the
+    /// offset of the next one.
+    pub fn instruction(&self, chunk: &Chunk, offset: usize) -> (String, usize) {
+        let line = self.line(chunk, offset);
+        let same_line = offset > 0 && self.line(chunk, offset - 1) == line;
+        let mut out = if same_line {
+            format!("{offset:04}    | ")
+        } else {
+            format!("{offset:04} {line:4} ")
+        };
+
+        let Ok(op) = OpCode::try_from(chunk.code[offset]) else {
+            let _ = write!(out, "<unknown opcode {}>", chunk.code[offset]);
+            return (out, offset + 1);
+        };
+        let name = format!("{op:?}");
+
+        let next = match op {
+            OpCode::Constant
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method => {
+                let index = chunk.read_u16(offset + 1);
+                let _ = write!(out, "{name:<16} {index:4} {}", chunk.constants[index as usize]);
+                offset + 3
+            }
+            OpCode::Subclass => {
+                let index = chunk.read_u16(offset + 1);
+                let superclass = chunk.read_u16(offset + 3);
+                let _ = write!(
+                    out,
+                    "{name:<16} {index:4} {} < {}",
+                    chunk.constants[index as usize], chunk.constants[superclass as usize],
+                );
+                offset + 5
+            }
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Call => {
+                let _ = write!(out, "{name:<16} {:4}", chunk.code[offset + 1]);
+                offset + 2
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                let jump = chunk.read_u16(offset + 1) as usize;
+                let target = if op == OpCode::Loop { offset + 3 - jump } else { offset + 3 + jump };
+                let _ = write!(out, "{name:<16} {offset:4} -> {target}");
+                offset + 3
+            }
+            OpCode::Closure => {
+                let index = chunk.read_u16(offset + 1);
+                let constant = &chunk.constants[index as usize];
+                let _ = write!(out, "{name:<16} {index:4} {constant}");
+                let upvalue_count = match constant {
+                    Constant::Function(proto) => proto.upvalue_count,
+                    _ => 0,
+                };
+                let mut next = offset + 3;
+                for _ in 0..upvalue_count {
+                    let kind = if chunk.code[next] == 1 { "local" } else { "upvalue" };
+                    let _ = write!(out, "\n{next:04}    |   {kind} {}", chunk.code[next + 1]);
+                    next += 2;
+                }
+                next
+            }
+            _ => {
+                out.push_str(&name);
+                offset + 1
+            }
+        };
+        (out, next)
+    }
+
+    fn line(&self, chunk: &Chunk, offset: usize) -> usize {
+        self.source_map.location(&chunk.spans[offset]).line
+    }
+}
+
+#[test]
+fn lists_every_instruction_with_its_line() {
+    use crate::{compiler::Compiler, parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    let source = "fn add(a, b) {\n  return a + b;\n}\nprint add(1, \"2\");";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Resolver::default().resolve(&program).unwrap();
+    let Ok(scripts) = Compiler::default().compile(&program) else {
+        panic!("expected the program to compile");
+    };
+    let source_map = SourceMap::new("test.lox", source);
+    let disassembler = Disassembler::new(&source_map);
+
+    let listing = disassembler.function(&scripts[0]);
+    assert!(listing.starts_with("== script ==\n0000    1 Closure"));
+    assert!(listing.contains("<fn add>"));
+    assert!(listing.contains("\n== add ==\n"));
+    assert!(listing.contains("    2 GetLocal            1\n"));
+    assert!(listing.contains("    | Add\n"));
+
+    let listing = disassembler.function(&scripts[1]);
+    assert!(listing.contains("    4 GetGlobal           0 \"add\"\n"));
+    assert!(listing.contains("Constant            2 \"2\"\n"));
+    assert!(listing.contains("Call                2\n"));
+}
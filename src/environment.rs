@@ -1,34 +1,178 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::{error::UnboundVariable, expr::eval::Value, Identifier};
+use crate::{
+    error::UnboundVariable,
+    expr::{eval::Value, Slot, Variable},
+    gc::{self, Trace, Tracer},
+    native::natives,
+    Identifier,
+};
 
-#[derive(Default)]
-pub struct Environment<'a> {
-    table: HashMap<Identifier, Value>,
-    enclosing: Option<&'a Environment<'a>>,
+/// A handle to a lexical scope. Scopes are reference counted, so a
+/// closure can keep the scope it was defined in alive after the
+/// block that created it has finished running.
+#[derive(Clone)]
+pub struct Environment(Rc<RefCell<Scope>>);
+
+enum Scope {
+    /// The outermost scope. Globals are looked up by name, since they
+    /// can be declared after the code that uses them is resolved.
+    Global(HashMap<Identifier, Value>),
+    /// A block or call scope. Locals are stored in the order they were
+    /// declared, which is the slot index the resolver gave them.
+    Local {
+        slots: Vec<Value>,
+        enclosing: Environment,
+    },
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        let globals = natives()
+            .into_iter()
+            .map(|native| (native.name.into(), Value::NativeFunction(native)))
+            .collect();
+
+        Self::new(Scope::Global(globals))
+    }
 }
 
-impl<'a> Environment<'a> {
-    pub fn push(&'a self) -> Self {
-        Self {
-            table: Default::default(),
-            enclosing: Some(self)
+impl Environment {
+    fn new(scope: Scope) -> Self {
+        Self(gc::alloc(RefCell::new(scope)))
+    }
+
+    /// Reports the scope this handle refers to.
+    pub fn trace(&self, tracer: &mut Tracer) {
+        tracer.edge(&self.0);
+    }
+
+    /// Creates a new scope nested inside this one.
+    pub fn push(&self) -> Self {
+        Self::new(Scope::Local {
+            slots: Vec::new(),
+            enclosing: self.clone(),
+        })
+    }
+
+    /// Declares a new variable in this scope.
+    pub fn bind(&self, name: Identifier, value: Value) {
+        match &mut *self.0.borrow_mut() {
+            Scope::Global(table) => {
+                table.insert(name, value);
+            }
+            Scope::Local { slots, .. } => slots.push(value),
+        }
+    }
+
+    /// Rebinds `variable` in the scope the resolver found it in.
+    pub fn assign(&self, variable: &Variable, value: Value) -> Result<(), UnboundVariable> {
+        match variable.slot.get() {
+            Some(slot) => {
+                *self.ancestor(slot.depth).local_mut(slot.index) = value;
+                Ok(())
+            }
+            None => match &mut *self.globals().0.borrow_mut() {
+                Scope::Global(table) => match table.get_mut(&variable.name) {
+                    Some(global) => {
+                        *global = value;
+                        Ok(())
+                    }
+                    None => Err(UnboundVariable(variable.name, variable.span.clone())),
+                },
+                Scope::Local { .. } => unreachable!("the outermost scope is global"),
+            },
         }
     }
 
-    pub fn bind(&mut self, name: Identifier, value: Value) {
-        self.table.insert(name, value);
+    pub fn get(&self, variable: &Variable) -> Result<Value, UnboundVariable> {
+        match variable.slot.get() {
+            Some(slot) => Ok(self.get_at(slot)),
+            None => match &*self.globals().0.borrow() {
+                Scope::Global(table) => table
+                    .get(&variable.name)
+                    .cloned()
+                    .ok_or_else(|| UnboundVariable(variable.name, variable.span.clone())),
+                Scope::Local { .. } => unreachable!("the outermost scope is global"),
+            },
+        }
+    }
+
+    /// Reads the global `name`, for tests.
+    #[cfg(test)]
+    pub fn global(&self, name: &str) -> Value {
+        self.get(&Variable::new(name.into(), Default::default()))
+            .unwrap_or_else(|_| panic!("`{name}` is not defined"))
     }
 
-    pub fn get(&self, name: Identifier) -> Result<&Value, UnboundVariable> {
-        self.get_rec(&name).ok_or(UnboundVariable(name))
+    pub fn get_at(&self, slot: Slot) -> Value {
+        self.ancestor(slot.depth).local_mut(slot.index).clone()
     }
 
-    fn get_rec(&self, name: &Identifier) -> Option<&Value> {
-        self.table.get(name).or(if let Some(ref env) = self.enclosing {
-            (&*env).get_rec(name)
-        } else {
-            None
+    fn ancestor(&self, depth: usize) -> Environment {
+        let mut env = self.clone();
+        for _ in 0..depth {
+            env = env.enclosing().expect("the resolver never points past the globals");
+        }
+        env
+    }
+
+    fn globals(&self) -> Environment {
+        let mut env = self.clone();
+        while let Some(enclosing) = env.enclosing() {
+            env = enclosing;
+        }
+        env
+    }
+
+    fn enclosing(&self) -> Option<Environment> {
+        match &*self.0.borrow() {
+            Scope::Global(_) => None,
+            Scope::Local { enclosing, .. } => Some(enclosing.clone()),
+        }
+    }
+
+    fn local_mut(&self, index: usize) -> std::cell::RefMut<'_, Value> {
+        std::cell::RefMut::map(self.0.borrow_mut(), |scope| match scope {
+            Scope::Local { slots, .. } => &mut slots[index],
+            Scope::Global(_) => unreachable!("resolved variables are always local"),
         })
     }
-}
\ No newline at end of file
+}
+
+impl Trace for RefCell<Scope> {
+    fn trace(&self, tracer: &mut Tracer) {
+        match &*self.try_borrow().expect(gc::BORROWED) {
+            Scope::Global(table) => table.values().for_each(|value| value.trace(tracer)),
+            Scope::Local { slots, enclosing } => {
+                slots.iter().for_each(|value| value.trace(tracer));
+                enclosing.trace(tracer);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        // Nothing can reach the scope anymore, so what's left in it doesn't matter.
+        *self.try_borrow_mut().expect(gc::BORROWED) = Scope::Global(HashMap::new());
+    }
+}
+
+#[test]
+fn assign_updates_enclosing_scope() {
+    use crate::token::Span;
+
+    let env = Environment::default();
+    env.bind("a".into(), Value::Number(1.));
+    let inner = env.push();
+    inner.bind("b".into(), Value::Number(1.));
+    let innermost = inner.push();
+
+    let b = Variable::new("b".into(), Span::default());
+    b.slot.set(Some(Slot { depth: 1, index: 0 }));
+    innermost.assign(&b, Value::Number(2.)).unwrap();
+    innermost.assign(&Variable::new("a".into(), Span::default()), Value::Number(2.)).unwrap();
+    assert!(innermost.assign(&Variable::new("c".into(), Span::default()), Value::Nil).is_err());
+
+    assert!(matches!(inner.get_at(Slot { depth: 0, index: 0 }), Value::Number(n) if n == 2.));
+    assert!(matches!(env.get(&Variable::new("a".into(), Span::default())), Ok(Value::Number(n)) if n == 2.));
+}
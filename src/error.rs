@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use crate::Identifier;
+use crate::{code::Code, lint::Warning, token::{Span, Token}, Identifier};
 
 #[derive(Debug, Error)]
 pub enum LoxError {
@@ -8,32 +8,162 @@ pub enum LoxError {
     ScannerError(Vec<ScannerError>),
     #[error(transparent)]
     ParserError(#[from] ParserError),
+    #[error("ResolverError")]
+    ResolverError(Vec<ResolverError>),
+    #[error("CompileError")]
+    CompileError(Vec<CompileError>),
+    #[error(transparent)]
+    LoadError(#[from] LoadError),
+    /// Warnings reported while they were denied.
+    #[error("Warnings")]
+    Warnings(Vec<Warning>),
     #[error(transparent)]
     RuntimeError(#[from] RuntimeError),
 }
 
 #[derive(Debug, Error)]
-#[error("ScannerError [{line}]: {message}")]
+#[error("ScannerError: {message}")]
 pub struct ScannerError {
-    pub line: usize,
+    pub code: Code,
+    pub span: Span,
+    pub message: String,
+}
+
+/// Every syntax error the parser recovered from in one run.
+#[derive(Debug, Error)]
+#[error("ParserError: {} syntax error(s)", .diagnostics.len())]
+pub struct ParserError {
+    pub diagnostics: Vec<SyntaxError>,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("{token} : {message}")]
+pub struct SyntaxError {
+    pub code: Code,
+    pub message: String,
+    pub token: Token,
+    pub span: Span,
+}
+
+#[derive(Debug, Error)]
+#[error("ResolverError: {message}")]
+pub struct ResolverError {
+    pub code: Code,
+    pub message: String,
+    pub span: Span,
+}
+
+/// A program that is valid Lox but doesn't fit the limits of the bytecode.
+#[derive(Debug, Error)]
+#[error("CompileError: {message}")]
+pub struct CompileError {
+    pub code: Code,
     pub message: String,
+    pub span: Span,
 }
 
+/// A compiled `.loxc` file that is corrupt, or was written by another version of rlox.
 #[derive(Debug, Error)]
-#[error("ParserError")]
-pub struct ParserError;
+#[error("LoadError: {message}")]
+pub struct LoadError {
+    pub message: String,
+}
 
 #[derive(Debug, Error)]
 #[error(transparent)]
 pub enum RuntimeError {
     UnboundVariable(#[from] UnboundVariable),
     TypeError(#[from] TypeError),
+    ArityError(#[from] ArityError),
+    NotCallable(#[from] NotCallable),
+    NotAnInstance(#[from] NotAnInstance),
+    UndefinedProperty(#[from] UndefinedProperty),
+    InvalidSuperclass(#[from] InvalidSuperclass),
+    StackOverflow(#[from] StackOverflow),
+}
+
+impl RuntimeError {
+    pub fn code(&self) -> Code {
+        match self {
+            RuntimeError::UnboundVariable(_) => Code::UnboundVariable,
+            RuntimeError::TypeError(_) => Code::TypeMismatch,
+            RuntimeError::ArityError(_) => Code::ArityMismatch,
+            RuntimeError::NotCallable(_) => Code::NotCallable,
+            RuntimeError::NotAnInstance(_) => Code::NotAnInstance,
+            RuntimeError::UndefinedProperty(_) => Code::UndefinedProperty,
+            RuntimeError::InvalidSuperclass(_) => Code::InvalidSuperclass,
+            RuntimeError::StackOverflow(_) => Code::StackOverflow,
+        }
+    }
+
+    /// Where in the source the error happened.
+    pub fn span(&self) -> &Span {
+        match self {
+            RuntimeError::UnboundVariable(UnboundVariable(_, span)) => span,
+            RuntimeError::TypeError(TypeError { span, .. }) => span,
+            RuntimeError::ArityError(ArityError { span, .. }) => span,
+            RuntimeError::NotCallable(NotCallable { span }) => span,
+            RuntimeError::NotAnInstance(NotAnInstance { span }) => span,
+            RuntimeError::UndefinedProperty(UndefinedProperty(_, span)) => span,
+            RuntimeError::InvalidSuperclass(InvalidSuperclass { span, .. }) => span,
+            RuntimeError::StackOverflow(StackOverflow { span }) => span,
+        }
+    }
+}
+
+/// A call that was still running when a runtime error was raised.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub function: Identifier,
+    pub call_site: Span,
+}
+
+#[derive(Debug, Error)]
+#[error("UnboundVariable: {0}")]
+pub struct UnboundVariable(pub Identifier, pub Span);
+
+#[derive(Debug, Error)]
+#[error("TypeError: cannot apply '{operator}' to {}", .operands.join(" and "))]
+pub struct TypeError {
+    pub operator: String,
+    /// The dynamic types of the operands, in order.
+    pub operands: Vec<&'static str>,
+    pub span: Span,
+}
+
+#[derive(Debug, Error)]
+#[error("ArityError: expected {expected} arguments but got {found}")]
+pub struct ArityError {
+    pub expected: usize,
+    pub found: usize,
+    pub span: Span,
 }
 
 #[derive(Debug, Error)]
-#[error("UboundVariable: {0}")]
-pub struct UnboundVariable(pub Identifier);
+#[error("NotCallable: can only call functions and classes")]
+pub struct NotCallable {
+    pub span: Span,
+}
+
+#[derive(Debug, Error)]
+#[error("NotAnInstance: only instances have properties")]
+pub struct NotAnInstance {
+    pub span: Span,
+}
+
+#[derive(Debug, Error)]
+#[error("UndefinedProperty: {0}")]
+pub struct UndefinedProperty(pub Identifier, pub Span);
 
 #[derive(Debug, Error)]
-#[error("TypeError")]
-pub struct TypeError;
\ No newline at end of file
+#[error("InvalidSuperclass: {name} is not a class")]
+pub struct InvalidSuperclass {
+    pub name: Identifier,
+    pub span: Span,
+}
+
+#[derive(Debug, Error)]
+#[error("StackOverflow: too many nested calls")]
+pub struct StackOverflow {
+    pub span: Span,
+}
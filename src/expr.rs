@@ -1,14 +1,46 @@
 pub mod display;
 pub mod eval;
 
-use crate::{token_kind::TokenKind, Identifier, LoxNumber};
+use std::{cell::Cell, rc::Rc};
 
-pub enum Expr {
+use crate::{token::{Span, Spanned}, token_kind::TokenKind, Identifier, LoxNumber};
+
+pub type Expr = Spanned<ExprKind>;
+
+pub enum ExprKind {
+    Assign(Variable, Box<Expr>),
     Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Get(Box<Expr>, Spanned<Identifier>),
     Grouping(Box<Expr>),
     Literal(Literal),
+    Logical(LogicalOp, Box<Expr>, Box<Expr>),
+    Self_(Variable),
+    Set(Box<Expr>, Spanned<Identifier>, Box<Expr>),
+    Super(Variable, Spanned<Identifier>),
     Unary(UnaryOp, Box<Expr>),
-    Variable(Identifier),
+    Variable(Variable),
+}
+
+/// A use of a variable. `slot` is filled in by the resolver; it stays
+/// `None` for globals, which are looked up by name at runtime.
+pub struct Variable {
+    pub name: Identifier,
+    pub span: Span,
+    pub slot: Cell<Option<Slot>>,
+}
+
+impl Variable {
+    pub fn new(name: Identifier, span: Span) -> Self {
+        Self { name, span, slot: Cell::new(None) }
+    }
+}
+
+/// Where a local lives: `depth` scopes out from the use, at position `index`.
+#[derive(Debug, Clone, Copy)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
 }
 
 pub enum BinaryOp {
@@ -22,6 +54,9 @@ pub enum BinaryOp {
     GreaterEqual,
     Less,
     LessEqual,
+}
+
+pub enum LogicalOp {
     And,
     Or,
 }
@@ -34,7 +69,7 @@ pub enum UnaryOp {
 pub enum Literal {
     Boolean(bool),
     Number(LoxNumber),
-    String(String),
+    String(Rc<str>),
     Nil,
 }
 
@@ -51,13 +86,21 @@ impl From<&TokenKind> for BinaryOp {
             TokenKind::GreaterEqual => BinaryOp::GreaterEqual,
             TokenKind::Less => BinaryOp::Less,
             TokenKind::LessEqual => BinaryOp::LessEqual,
-            TokenKind::And => BinaryOp::And,
-            TokenKind::Or => BinaryOp::Or,
             _ => panic!("{:?} should be a binary operator", value),
         }
     }
 }
 
+impl From<&TokenKind> for LogicalOp {
+    fn from(value: &TokenKind) -> Self {
+        match value {
+            TokenKind::And => LogicalOp::And,
+            TokenKind::Or => LogicalOp::Or,
+            _ => panic!("{:?} should be a logical operator", value),
+        }
+    }
+}
+
 impl From<&TokenKind> for UnaryOp {
     fn from(value: &TokenKind) -> Self {
         match value {
@@ -70,11 +113,12 @@ impl From<&TokenKind> for UnaryOp {
 
 #[test]
 fn print_ast() {
-    let expr = Expr::Binary(
+    let node = |kind| Expr::new(kind, Span::default());
+    let expr = node(ExprKind::Binary(
         BinaryOp::Mul,
-        Expr::Unary(UnaryOp::Minus, Expr::Literal(Literal::Number(123.)).into()).into(),
-        Expr::Grouping(Expr::Literal(Literal::Number(45.67)).into()).into(),
-    );
+        node(ExprKind::Unary(UnaryOp::Minus, node(ExprKind::Literal(Literal::Number(123.))).into())).into(),
+        node(ExprKind::Grouping(node(ExprKind::Literal(Literal::Number(45.67))).into())).into(),
+    ));
 
-    println!("{expr}");
+    assert_eq!(expr.to_string(), "(* (- 123) (group 45.67))");
 }
@@ -1,21 +1,40 @@
 use std::fmt::Display;
 
-use crate::expr::{Literal, UnaryOp};
+use crate::expr::{Literal, LogicalOp, UnaryOp, Variable};
 
-use super::{BinaryOp, Expr};
+use super::{BinaryOp, ExprKind};
 
-impl Display for Expr {
+impl Display for ExprKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expr::Binary(l, op, r) => write!(f, "({op} {l} {r})"),
-            Expr::Grouping(expr) => write!(f, "(group {expr})"),
-            Expr::Literal(lit) => write!(f, "{lit}"),
-            Expr::Unary(op, expr) => write!(f, "({op} {expr})"),
-            Expr::Variable(name) => write!(f, "({name})"),
+            ExprKind::Assign(name, expr) => write!(f, "(= {name} {expr})"),
+            ExprKind::Binary(op, l, r) => write!(f, "({op} {l} {r})"),
+            ExprKind::Call(callee, args) => {
+                write!(f, "(call {callee}")?;
+                for arg in args {
+                    write!(f, " {arg}")?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Get(object, name) => write!(f, "(. {object} {name})"),
+            ExprKind::Grouping(expr) => write!(f, "(group {expr})"),
+            ExprKind::Literal(lit) => write!(f, "{lit}"),
+            ExprKind::Logical(op, l, r) => write!(f, "({op} {l} {r})"),
+            ExprKind::Self_(_) => write!(f, "self"),
+            ExprKind::Set(object, name, value) => write!(f, "(= (. {object} {name}) {value})"),
+            ExprKind::Super(_, method) => write!(f, "(super {method})"),
+            ExprKind::Unary(op, expr) => write!(f, "({op} {expr})"),
+            ExprKind::Variable(name) => write!(f, "({name})"),
         }
     }
 }
 
+impl Display for Variable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
 impl Display for BinaryOp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -32,8 +51,19 @@ impl Display for BinaryOp {
                 BinaryOp::GreaterEqual => ">=",
                 BinaryOp::Less => "<",
                 BinaryOp::LessEqual => "<=",
-                BinaryOp::And => "&",
-                BinaryOp::Or => "|",
+            }
+        )
+    }
+}
+
+impl Display for LogicalOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                LogicalOp::And => "and",
+                LogicalOp::Or => "or",
             }
         )
     }
@@ -60,7 +90,7 @@ impl Display for Literal {
             match self {
                 Literal::Boolean(value) => value.to_string(),
                 Literal::Number(value) => value.to_string(),
-                Literal::String(value) => value.to_owned(),
+                Literal::String(value) => value.to_string(),
                 Literal::Nil => "nil".to_string(),
             }
         )
@@ -1,19 +1,98 @@
-use std::fmt::Display;
+use std::{cell::RefCell, fmt::Display, mem, rc::Rc};
 
-use crate::{environment::Environment, error::{RuntimeError, TypeError}, LoxNumber};
+use crate::{
+    class::{self, Class, Instance},
+    environment::Environment,
+    error::{ArityError, Frame, NotAnInstance, NotCallable, RuntimeError, StackOverflow, TypeError, UndefinedProperty},
+    function::Function,
+    gc::{self, Tracer},
+    native::NativeFunction,
+    token::Span,
+    LoxNumber,
+};
 
-use super::{Expr, Literal, UnaryOp, BinaryOp};
+use super::{Expr, ExprKind, Literal, LogicalOp, Slot, UnaryOp, BinaryOp};
+
+/// Calls can nest this deep before the next one fails with a stack overflow.
+pub const MAX_CALL_DEPTH: usize = 512;
+
+/// The tree-walker recurses on the Rust stack for every call, and for every
+/// statement and expression nested in its body. This leaves room for
+/// [`MAX_CALL_DEPTH`] calls of deeply nested code in a debug build.
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+thread_local! {
+    /// The calls currently being evaluated, innermost last.
+    static CALL_STACK: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Takes the frames that were active when the last runtime error was
+/// raised, innermost last, and leaves the call stack empty.
+pub fn take_backtrace() -> Vec<Frame> {
+    CALL_STACK.with(|stack| mem::take(&mut *stack.borrow_mut()))
+}
 
 impl Expr {
-    pub fn eval(self, env: &Environment) -> Result<Value, RuntimeError> {
-        match self {
-            Expr::Binary(op, expr1, expr2) => Ok(op.eval(expr1.eval(env)?, expr2.eval(env)?)?),
-            Expr::Grouping(expr) => expr.eval(env),
-            Expr::Literal(lit) =>   Ok(lit.into()),
-            Expr::Unary(op, expr) => Ok(op.eval(expr.eval(env)?)?),
+    pub fn eval(&self, env: &Environment) -> Result<Value, RuntimeError> {
+        match &self.node {
+            ExprKind::Assign(variable, expr) => {
+                let value = expr.eval(env)?;
+                env.assign(variable, value.clone())?;
+                Ok(value)
+            }
+            ExprKind::Binary(op, expr1, expr2) => Ok(op.eval(expr1.eval(env)?, expr2.eval(env)?, &self.span)?),
+            ExprKind::Call(callee, args) => {
+                let callee = callee.eval(env)?;
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(env))
+                    .collect::<Result<Vec<_>, _>>()?;
+                callee.call(args, &self.span)
+            }
+            ExprKind::Get(object, name) => match object.eval(env)? {
+                Value::Instance(instance) => instance
+                    .get(name.node)
+                    .ok_or_else(|| UndefinedProperty(name.node, name.span.clone()).into()),
+                _ => Err(NotAnInstance { span: name.span.clone() }.into()),
+            },
+            ExprKind::Grouping(expr) => expr.eval(env),
+            ExprKind::Literal(lit) =>   Ok(lit.into()),
+            ExprKind::Logical(op, expr1, expr2) => {
+                // Short-circuit and return the operand that decided the result.
+                let left = expr1.eval(env)?;
+                match op {
+                    LogicalOp::Or if left.is_truthy() => Ok(left),
+                    LogicalOp::And if !left.is_truthy() => Ok(left),
+                    _ => expr2.eval(env),
+                }
+            }
+            ExprKind::Self_(variable) => Ok(env.get(variable)?),
+            ExprKind::Set(object, name, value) => {
+                // The value is evaluated before the target is checked, as the VM does.
+                let object = object.eval(env)?;
+                let value = value.eval(env)?;
+                let Value::Instance(instance) = object else {
+                    return Err(NotAnInstance { span: name.span.clone() }.into());
+                };
+                instance.set(name.node, value.clone());
+                Ok(value)
+            }
+            ExprKind::Super(variable, method) => {
+                let slot = variable.slot.get().expect("`super` is always a local");
+                // `self` is bound in the scope just inside the one that binds `super`.
+                let self_slot = Slot { depth: slot.depth - 1, index: 0 };
+                let (Value::Class(superclass), Value::Instance(instance)) = (env.get_at(slot), env.get_at(self_slot)) else {
+                    unreachable!("`super` must be a class and `self` an instance");
+                };
+                match superclass.find_method(method.node) {
+                    Some(function) => Ok(Value::Function(gc::alloc(function.bind(instance)))),
+                    None => Err(UndefinedProperty(method.node, method.span.clone()).into()),
+                }
+            }
+            ExprKind::Unary(op, expr) => Ok(op.eval(expr.eval(env)?, &self.span)?),
 
             // There is an oportunity for optimization here. Distinguish between OwenedValue, RefValue, and MutRefValue.
-            Expr::Variable(name) => Ok(env.get(name)?.clone())
+            ExprKind::Variable(variable) => Ok(env.get(variable)?)
         }
     }
 }
@@ -22,59 +101,140 @@ impl Expr {
 pub enum Value {
     Boolean(bool),
     Number(LoxNumber),
-    String(String),
+    String(Rc<str>),
+    Function(Rc<Function>),
+    NativeFunction(NativeFunction<Value>),
+    Class(Rc<Class>),
+    Instance(Instance),
     Nil,
 }
 
+impl Value {
+    /// Lox truthiness: `nil` and `false` are falsy, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    /// Reports the heap object this value refers to, if it refers to one.
+    pub fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Value::Function(function) => tracer.edge(function),
+            Value::Class(class) => tracer.edge(class),
+            Value::Instance(instance) => instance.trace(tracer),
+            _ => (),
+        }
+    }
+
+    /// The name of the value's dynamic type, as shown in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Boolean(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::NativeFunction(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Nil => "nil",
+        }
+    }
+
+    fn call(&self, args: Vec<Value>, span: &Span) -> Result<Value, RuntimeError> {
+        let arity = match self {
+            Value::Function(function) => function.arity(),
+            Value::NativeFunction(native) => native.arity,
+            Value::Class(class) => class.arity(),
+            _ => return Err(NotCallable { span: span.clone() }.into()),
+        };
+
+        if args.len() != arity {
+            let span = span.clone();
+            return Err(ArityError { expected: arity, found: args.len(), span }.into());
+        }
+
+        if CALL_STACK.with(|stack| stack.borrow().len()) >= MAX_CALL_DEPTH {
+            return Err(StackOverflow { span: span.clone() }.into());
+        }
+
+        let function = match self {
+            Value::Function(function) => function.decl.name.node,
+            Value::NativeFunction(native) => native.name.into(),
+            Value::Class(class) => class.name,
+            _ => unreachable!(),
+        };
+        CALL_STACK.with(|stack| stack.borrow_mut().push(Frame { function, call_site: span.clone() }));
+
+        let result = match self {
+            Value::Function(function) => function.call(args),
+            Value::NativeFunction(native) => native.call(args),
+            Value::Class(class) => class::instantiate(class, args),
+            _ => unreachable!(),
+        };
+
+        // A failed call keeps its frame so the error can be traced back through it.
+        if result.is_ok() {
+            CALL_STACK.with(|stack| stack.borrow_mut().pop());
+        }
+        result
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Boolean(x), Value::Boolean(y)) => x == y,
+            (Value::Number(x), Value::Number(y)) => x == y,
+            (Value::String(x), Value::String(y)) => x == y,
+            (Value::Function(x), Value::Function(y)) => Rc::ptr_eq(x, y),
+            (Value::NativeFunction(x), Value::NativeFunction(y)) => x.name == y.name,
+            (Value::Class(x), Value::Class(y)) => Rc::ptr_eq(x, y),
+            (Value::Instance(x), Value::Instance(y)) => x == y,
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
 impl UnaryOp {
-    fn eval(self, x: Value) -> Result<Value, TypeError> {
+    fn eval(&self, x: Value, span: &Span) -> Result<Value, TypeError> {
         use Value::*;
+        let operands = vec![x.type_name()];
         let res: Value = match (self, x) {
             (UnaryOp::Minus, Number(n)) => Number(-n),
             (UnaryOp::Not, Boolean(b))  => Boolean(!b),
-            _ => return Err(TypeError),
+            _ => return Err(TypeError { operator: self.to_string(), operands, span: span.clone() }),
         };
         Ok(res)
     }
 }
 
 impl BinaryOp {
-    fn eval(self, x: Value, y: Value) -> Result<Value, TypeError> {
+    fn eval(&self, x: Value, y: Value, span: &Span) -> Result<Value, TypeError> {
         use Value::*;
+        let operands = vec![x.type_name(), y.type_name()];
         let res: Value = match (self, x, y) {
             (BinaryOp::Minus, Number(x), Number(y))        => Number(x - y),
             (BinaryOp::Plus,  Number(x), Number(y))        => Number(x + y),
-            (BinaryOp::Plus,  String(x), String(y))        => String(x + &y),
+            (BinaryOp::Plus,  String(x), String(y))        => String(format!("{x}{y}").into()),
             (BinaryOp::Div,   Number(x), Number(y))        => Number(x / y),
             (BinaryOp::Mul,   Number(x), Number(y))        => Number(x * y),
-            (BinaryOp::NotEqual, Boolean(x), Boolean(y))   => Boolean(x != y),
-            (BinaryOp::NotEqual, Number(x), Number(y))     => Boolean(x != y),
-            (BinaryOp::NotEqual, String(x), String(y))     => Boolean(x != y),
-            (BinaryOp::NotEqual, Nil, Nil)                 => Boolean(false),
-            (BinaryOp::NotEqual, _, _)                     => Boolean(true),
-            (BinaryOp::Equal, Boolean(x), Boolean(y))      => Boolean(x == y),
-            (BinaryOp::Equal, Number(x), Number(y))        => Boolean(x == y),
-            (BinaryOp::Equal, String(x), String(y))        => Boolean(x == y),
-            (BinaryOp::Equal, Nil, Nil)                    => Boolean(true),
-            (BinaryOp::Equal, _, _)                        => Boolean(false),
+            (BinaryOp::NotEqual, x, y)                     => Boolean(x != y),
+            (BinaryOp::Equal,    x, y)                     => Boolean(x == y),
             (BinaryOp::Less,         Number(x), Number(y)) => Boolean(x < y),
             (BinaryOp::Greater,      Number(x), Number(y)) => Boolean(x > y),
             (BinaryOp::LessEqual,    Number(x), Number(y)) => Boolean(x <= y),
             (BinaryOp::GreaterEqual, Number(x), Number(y)) => Boolean(x >= y),
-            (BinaryOp::And, Boolean(x), Boolean(y))        => Boolean(x && y),
-            (BinaryOp::Or,  Boolean(x), Boolean(y))        => Boolean(x || y),
-            _ => return Err(TypeError),
+            _ => return Err(TypeError { operator: self.to_string(), operands, span: span.clone() }),
         };
         Ok(res)
     }
 }
 
-impl From<Literal> for Value {
-    fn from(value: Literal) -> Self {
+impl From<&Literal> for Value {
+    fn from(value: &Literal) -> Self {
         match value {
-            Literal::Boolean(b) => Self::Boolean(b),
-            Literal::Number(n) => Self::Number(n),
-            Literal::String(s) => Self::String(s),
+            Literal::Boolean(b) => Self::Boolean(*b),
+            Literal::Number(n) => Self::Number(*n),
+            Literal::String(s) => Self::String(Rc::clone(s)),
             Literal::Nil => Self::Nil,
         }
     }
@@ -88,9 +248,68 @@ impl Display for Value {
             match self {
                 Value::Boolean(value) => value.to_string(),
                 Value::Number(value) => value.to_string(),
-                Value::String(value) => value.to_owned(),
+                Value::String(value) => value.to_string(),
+                Value::Function(function) => function.to_string(),
+                Value::NativeFunction(native) => native.to_string(),
+                Value::Class(class) => class.to_string(),
+                Value::Instance(instance) => instance.to_string(),
                 Value::Nil => "nil".to_string(),
             }
         )
     }
-}
\ No newline at end of file
+}
+#[test]
+fn type_errors_name_operator_and_operands() {
+    let span = Span::new(3, 5);
+    let Err(err) = BinaryOp::Minus.eval(Value::String("a".into()), Value::Number(1.), &span) else {
+        panic!("expected a type error");
+    };
+
+    assert_eq!(err.to_string(), "TypeError: cannot apply '-' to string and number");
+    assert_eq!(err.span.offset(), 3);
+}
+
+#[test]
+fn failed_calls_leave_their_frames() {
+    use crate::{parser::Parser, resolver::Resolver, scanner::Scanner, statement::eval::eval_block};
+
+    let source = "fn inner() { return -\"x\"; } fn outer() { return inner(); } outer();";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let Ok(program) = Parser::new(tokens).parse() else {
+        panic!("expected a program");
+    };
+    Resolver::default().resolve(&program).unwrap();
+
+    assert!(eval_block(&program, &Environment::default()).is_err());
+    let functions: Vec<_> = take_backtrace().into_iter().map(|frame| frame.function).collect();
+    assert_eq!(functions, ["outer", "inner"]);
+    assert!(take_backtrace().is_empty());
+}
+
+#[test]
+fn logical_operators_short_circuit_and_return_an_operand() {
+    use crate::statement::eval::run_source;
+
+    let (env, result) = run_source("
+        let calls = 0;
+        fn touch() { calls = calls + 1; return \"touched\"; }
+        let a = false and touch();
+        let b = true or touch();
+        let c = nil or \"x\";
+        let d = \"y\" and 2;
+        let e = 0 or touch();
+        let f = nil and touch();
+        let g = false or touch();
+    ");
+
+    assert!(result.unwrap().is_continue());
+    assert_eq!(env.global("a").to_string(), "false");
+    assert_eq!(env.global("b").to_string(), "true");
+    assert_eq!(env.global("c").to_string(), "x");
+    assert_eq!(env.global("d").to_string(), "2");
+    assert_eq!(env.global("e").to_string(), "0");
+    assert!(matches!(env.global("f"), Value::Nil));
+    assert_eq!(env.global("g").to_string(), "touched");
+    // Only `g` had to evaluate its right operand.
+    assert_eq!(env.global("calls").to_string(), "1");
+}
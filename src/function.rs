@@ -0,0 +1,197 @@
+use std::{fmt::Display, ops::ControlFlow, rc::Rc};
+
+use crate::{
+    class::{Class, Instance}, environment::Environment, error::RuntimeError,
+    expr::{eval::Value, Slot},
+    gc::{self, Trace, Tracer},
+    native::NativeValue,
+    statement::{eval::eval_block, FunctionDecl},
+};
+
+/// A user-defined function, created when a `fn` declaration is evaluated.
+/// It captures the environment it was declared in.
+pub struct Function {
+    pub decl: Rc<FunctionDecl>,
+    closure: Environment,
+    is_initializer: bool,
+}
+
+impl Function {
+    pub fn new(decl: Rc<FunctionDecl>, closure: Environment) -> Self {
+        Self { decl, closure, is_initializer: false }
+    }
+
+    /// Creates a function for a method body. The `init` method is an initializer
+    /// and always returns the instance it was called on.
+    pub fn method(decl: Rc<FunctionDecl>, closure: Environment) -> Self {
+        let is_initializer = *decl.name == "init";
+        Self { decl, closure, is_initializer }
+    }
+
+    /// Returns a copy of this method whose `self` refers to `instance`.
+    pub fn bind(&self, instance: Instance) -> Self {
+        let closure = self.closure.push();
+        closure.bind("self".into(), Value::Instance(instance));
+        Self {
+            decl: Rc::clone(&self.decl),
+            closure,
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.decl.params.len()
+    }
+
+    pub fn call(&self, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let env = self.closure.push();
+        for (param, arg) in self.decl.params.iter().zip(args) {
+            env.bind(param.node, arg);
+        }
+
+        let value = match eval_block(&self.decl.body, &env)? {
+            ControlFlow::Break(value) => value,
+            ControlFlow::Continue(()) => Value::Nil,
+        };
+
+        if self.is_initializer {
+            // `self` is the only variable in the scope created by `bind`.
+            return Ok(self.closure.get_at(Slot { depth: 0, index: 0 }));
+        }
+        Ok(value)
+    }
+}
+
+impl Trace for Function {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.closure.trace(tracer);
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.decl.name)
+    }
+}
+
+impl NativeValue for Value {
+    fn number(n: f64) -> Self {
+        Value::Number(n)
+    }
+
+    fn record(class: &str, fields: &[(&str, f64)]) -> Self {
+        let instance = Instance::new(gc::alloc(Class::new(class.into(), None, Default::default())));
+        for &(name, n) in fields {
+            instance.set(name.into(), Value::Number(n));
+        }
+        Value::Instance(instance)
+    }
+}
+
+#[test]
+fn closures_capture_their_environment() {
+    use crate::{expr::Variable, parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    let source = "
+        fn makeCounter() {
+            let i = 0;
+            fn count() { i = i + 1; return i; }
+            return count;
+        }
+        let counter = makeCounter();
+        counter();
+        let n = counter();
+    ";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Resolver::default().resolve(&program).unwrap();
+    let env = Environment::default();
+    assert!(eval_block(&program, &env).unwrap().is_continue());
+
+    assert!(matches!(env.get(&Variable::new("n".into(), Default::default())), Ok(Value::Number(n)) if n == 2.));
+}
+
+#[test]
+fn calls_bind_arguments_and_return_values() {
+    use crate::statement::eval::run_source;
+
+    let (env, result) = run_source("
+        fn add(a, b) { return a + b; }
+        fn firstAbove(limit) {
+            let n = 0;
+            while (true) {
+                if (n > limit) return n;
+                n = n + 1;
+            }
+        }
+        fn bare() { return; }
+        fn empty() {}
+        let f = add;
+        let sum = f(1, 2);
+        let joined = add(\"a\", \"b\");
+        let first = firstAbove(2);
+        let a = bare();
+        let b = empty();
+    ");
+
+    assert!(result.unwrap().is_continue());
+    assert_eq!(env.global("sum").to_string(), "3");
+    assert_eq!(env.global("joined").to_string(), "ab");
+    assert_eq!(env.global("first").to_string(), "3");
+    assert!(matches!(env.global("a"), Value::Nil));
+    assert!(matches!(env.global("b"), Value::Nil));
+}
+
+#[test]
+fn closures_share_the_variables_they_capture() {
+    use crate::statement::eval::run_source;
+
+    let (env, result) = run_source("
+        fn makeCounter() {
+            let i = 0;
+            fn count() { i = i + 1; return i; }
+            return count;
+        }
+        let a = makeCounter();
+        let b = makeCounter();
+        a();
+        a();
+        let fromA = a();
+        let fromB = b();
+
+        let x = \"before\";
+        fn show() { return x; }
+        x = \"after\";
+        let shown = show();
+    ");
+
+    assert!(result.unwrap().is_continue());
+    assert_eq!(env.global("fromA").to_string(), "3");
+    assert_eq!(env.global("fromB").to_string(), "1");
+    assert_eq!(env.global("shown").to_string(), "after");
+}
+
+#[test]
+fn runaway_recursion_is_a_runtime_error() {
+    use std::thread;
+
+    use crate::{
+        code::Code,
+        expr::eval::{take_backtrace, MAX_CALL_DEPTH, STACK_SIZE},
+        statement::eval::run_source,
+    };
+
+    // The test harness's threads are too small for `MAX_CALL_DEPTH` calls.
+    let interpreter = thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+        let source = "fn f(n) { return f(n + 1); } f(0);";
+        let (_, result) = run_source(source);
+        let Err(err) = result else {
+            panic!("expected a stack overflow");
+        };
+
+        assert_eq!(err.code(), Code::StackOverflow);
+        assert_eq!(err.span().offset(), source.find("f(n + 1)").unwrap());
+        assert_eq!(take_backtrace().len(), MAX_CALL_DEPTH);
+    });
+    interpreter.unwrap().join().unwrap();
+}
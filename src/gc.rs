@@ -0,0 +1,272 @@
+//! A mark-and-sweep collector for the objects both backends share through
+//! `Rc`: environments, functions, classes, instances and, in the VM,
+//! closures and upvalues. Reference counting frees most of them on its
+//! own; the collector finds the cycles it can't, like an instance holding a
+//! closure that captured the instance, and breaks them.
+//!
+//! Every object is created with [`alloc`], which registers it in the heap.
+//! The roots are the objects referenced from outside the heap: the
+//! environment chain and values of the code that is running, the VM stack,
+//! and any value a Rust caller is holding on to. Rather than listing them,
+//! a collection finds them by subtracting the references objects hold to
+//! each other from their strong counts; whatever has references left is
+//! reachable from outside. Objects that can't be reached from a root are
+//! garbage, and are cleared so the cycles through them drop.
+//!
+//! Collections only happen inside [`alloc`], and nothing holds a borrow of
+//! an object's `RefCell` across a call to it, so a collection can look
+//! inside every object. A [`Trace`] impl that finds its object borrowed
+//! panics with [`BORROWED`] instead of skipping it, since the objects it
+//! would have reported could then be cleared while still in use.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+/// Collect once this many objects are tracked, at the least.
+const INITIAL_THRESHOLD: usize = 1024;
+
+/// After a collection, the heap may grow to this many times the objects that survived.
+const GROW_FACTOR: usize = 2;
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap::default());
+}
+
+pub const BORROWED: &str = "a heap object was borrowed across a call to `gc::alloc`";
+
+/// An object that can hold references to other heap objects.
+pub trait Trace {
+    /// Reports every heap object this one holds a strong reference to,
+    /// once per reference.
+    fn trace(&self, tracer: &mut Tracer);
+
+    /// Drops the references this object holds, once it is known to be garbage.
+    /// Objects that can't be changed can't close a cycle, and need not clear anything.
+    fn clear(&self) {}
+}
+
+/// Receives the references reported by [`Trace::trace`].
+pub struct Tracer<'a> {
+    visit: &'a mut dyn FnMut(usize),
+}
+
+impl Tracer<'_> {
+    pub fn edge<T: ?Sized>(&mut self, object: &Rc<T>) {
+        (self.visit)(address(object));
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    pub collections: usize,
+    /// Objects freed by breaking cycles, over all collections.
+    pub collected: usize,
+    /// Objects alive right now.
+    pub live: usize,
+}
+
+struct Heap {
+    objects: Vec<Weak<dyn Trace>>,
+    /// Collect when more objects than this are tracked.
+    next_gc: usize,
+    /// Collect on every allocation, to flush out objects that aren't rooted.
+    stress: bool,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            objects: Vec::new(),
+            next_gc: INITIAL_THRESHOLD,
+            stress: false,
+            stats: GcStats::default(),
+        }
+    }
+}
+
+/// Moves `object` to the heap, collecting first if the heap is full.
+pub fn alloc<T: Trace + 'static>(object: T) -> Rc<T> {
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        if heap.stress || heap.objects.len() >= heap.next_gc {
+            heap.collect();
+        }
+    });
+
+    let object = Rc::new(object);
+    let weak: Weak<dyn Trace> = Rc::downgrade(&object) as Weak<dyn Trace>;
+    HEAP.with(|heap| heap.borrow_mut().objects.push(weak));
+    object
+}
+
+/// Collects now, whatever the allocation pressure.
+pub fn collect() {
+    HEAP.with(|heap| heap.borrow_mut().collect());
+}
+
+/// Makes every allocation collect, for `--gc-stress`.
+pub fn set_stress(stress: bool) {
+    HEAP.with(|heap| heap.borrow_mut().stress = stress);
+}
+
+pub fn stats() -> GcStats {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        let live = heap.objects.iter().filter(|object| object.strong_count() > 0).count();
+        GcStats { live, ..heap.stats }
+    })
+}
+
+impl Heap {
+    fn collect(&mut self) {
+        self.objects.retain(|object| object.strong_count() > 0);
+        let objects: Vec<Rc<dyn Trace>> = self.objects.iter().filter_map(Weak::upgrade).collect();
+        let index: HashMap<usize, usize> = objects.iter().enumerate().map(|(i, object)| (address(object), i)).collect();
+
+        // Whatever is left of an object's count after removing the references
+        // from other objects comes from outside the heap. `- 1` is `objects`.
+        let mut outside: Vec<usize> = objects.iter().map(|object| Rc::strong_count(object) - 1).collect();
+        for object in &objects {
+            object.trace(&mut Tracer {
+                visit: &mut |to| {
+                    if let Some(&i) = index.get(&to) {
+                        outside[i] = outside[i].saturating_sub(1);
+                    }
+                },
+            });
+        }
+
+        // Mark.
+        let mut gray: Vec<usize> = (0..objects.len()).filter(|&i| outside[i] > 0).collect();
+        let mut marked = vec![false; objects.len()];
+        for &i in &gray {
+            marked[i] = true;
+        }
+        while let Some(i) = gray.pop() {
+            objects[i].trace(&mut Tracer {
+                visit: &mut |to| {
+                    if let Some(&j) = index.get(&to) {
+                        if !marked[j] {
+                            marked[j] = true;
+                            gray.push(j);
+                        }
+                    }
+                },
+            });
+        }
+
+        // Sweep. Clearing drops the references that kept the garbage alive;
+        // `objects` holds the last ones.
+        let mut collected = 0;
+        for (object, marked) in objects.iter().zip(&marked) {
+            if !marked {
+                object.clear();
+                collected += 1;
+            }
+        }
+        drop(objects);
+
+        self.objects.retain(|object| object.strong_count() > 0);
+        self.next_gc = (self.objects.len() * GROW_FACTOR).max(INITIAL_THRESHOLD);
+        self.stats.collections += 1;
+        self.stats.collected += collected;
+    }
+}
+
+fn address<T: ?Sized>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
+
+#[test]
+fn collects_cycles_but_not_reachable_objects() {
+    use crate::{
+        environment::Environment,
+        expr::{eval::Value, Variable},
+        parser::Parser,
+        resolver::Resolver,
+        scanner::Scanner,
+        statement::eval::eval_block,
+    };
+
+    let source = "
+        class Node {}
+        fn leak() {
+            let node = Node();
+            fn f() { return node; }
+            node.f = f;
+        }
+        fn keep() {
+            let node = Node();
+            fn f() { return node; }
+            node.f = f;
+            return node;
+        }
+        let i = 0;
+        while (i < 10) { leak(); i = i + 1; }
+        let kept = keep();
+    ";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Resolver::default().resolve(&program).unwrap();
+    let env = Environment::default();
+    assert!(eval_block(&program, &env).unwrap().is_continue());
+
+    let before = stats();
+    collect();
+    let after = stats();
+    // Each leaked call leaves a cycle of the call's scope, the instance and `f`.
+    assert!(after.collected - before.collected >= 30);
+    assert_eq!(after.live, before.live - (after.collected - before.collected));
+
+    let kept = env.get(&Variable::new("kept".into(), Default::default())).unwrap();
+    let Value::Instance(kept) = kept else {
+        panic!("expected an instance");
+    };
+    assert!(kept.get("f".into()).is_some());
+}
+
+#[test]
+fn collecting_during_a_method_call_keeps_the_receiver() {
+    use crate::{compiler::Compiler, parser::Parser, resolver::Resolver, scanner::Scanner, statement::eval::run_source, vm::Vm};
+
+    // The receiver is a temporary, so while `bump` runs only the bound
+    // method's scope refers to it.
+    let source = "
+        class Counter {
+            init() { self.count = 0; }
+            bump() {
+                fn add() { self.count = self.count + 1; }
+                let i = 0;
+                while (i < 5) { add(); Counter(); i = i + 1; }
+                return self.count + self.get();
+            }
+            get() { return self.count; }
+        }
+        let total = Counter().bump();
+    ";
+    set_stress(true);
+    let before = stats().collections;
+
+    let (env, result) = run_source(source);
+    assert!(result.unwrap().is_continue());
+    assert_eq!(env.global("total").to_string(), "10");
+
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Resolver::default().resolve(&program).unwrap();
+    let Ok(scripts) = Compiler::default().compile(&program) else {
+        panic!("expected the program to compile");
+    };
+    let mut vm = Vm::default();
+    for script in scripts {
+        assert!(vm.run(script).is_ok());
+    }
+    assert_eq!(vm.global("total").to_string(), "10");
+
+    set_stress(false);
+    assert!(stats().collections - before > 50);
+}
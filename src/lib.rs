@@ -1,12 +1,27 @@
+pub mod chunk;
+pub mod class;
+pub mod code;
+pub mod compiler;
+pub mod diagnostic;
+pub mod disassembler;
 pub mod environment;
 pub mod error;
 pub mod expr;
+pub mod function;
+pub mod gc;
+pub mod lint;
 pub mod lox;
+pub mod loxc;
+pub mod native;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
+pub mod source_map;
 pub mod statement;
+pub mod symbol;
 pub mod token;
 pub mod token_kind;
+pub mod vm;
 
 type LoxNumber = f64;
-type Identifier = String;
\ No newline at end of file
+type Identifier = symbol::Symbol;
\ No newline at end of file
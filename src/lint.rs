@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use crate::{
+    code::Code,
+    expr::{Expr, ExprKind, Literal},
+    statement::{FunctionDecl, Statement, StatementKind},
+    token::{Span, Spanned},
+    Identifier,
+};
+
+/// A suspicious but legal piece of code found by the [`Linter`].
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub code: Code,
+    pub message: String,
+    pub span: Span,
+}
+
+/// A static pass over the parsed program that looks for code that is
+/// probably a mistake. Unlike the [`Resolver`](crate::resolver::Resolver)
+/// it never stops the program from running, unless warnings are denied.
+#[derive(Default)]
+pub struct Linter {
+    /// The innermost scope is last. The first one holds the globals.
+    scopes: Vec<HashMap<Identifier, Binding>>,
+    warnings: Vec<Warning>,
+}
+
+struct Binding {
+    span: Span,
+    /// Only local `let` bindings are reported when unused: globals may
+    /// still be read by code typed later into the prompt.
+    check_unused: bool,
+    used: bool,
+}
+
+impl Linter {
+    /// Returns the warnings for `program`, in source order.
+    pub fn lint(mut self, program: &[Statement]) -> Vec<Warning> {
+        self.scopes.push(HashMap::new());
+        self.block(program);
+        self.scopes.pop();
+
+        self.warnings.sort_by_key(|warning| warning.span.offset());
+        self.warnings
+    }
+
+    fn block(&mut self, stmts: &[Statement]) {
+        let mut diverged = false;
+        for stmt in stmts {
+            if diverged {
+                // Only the first unreachable statement, the rest follow from it.
+                self.warn(Code::UnreachableCode, "Unreachable code.".into(), &stmt.span);
+                diverged = false;
+            }
+            self.statement(stmt);
+            diverged |= always_returns(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match &stmt.node {
+            StatementKind::Block(stmts) => {
+                self.begin_scope();
+                self.block(stmts);
+                self.end_scope();
+            }
+            StatementKind::Class(decl) => {
+                self.declare(&decl.name, false);
+                if let Some(superclass) = &decl.superclass {
+                    self.read(superclass.name);
+                }
+                for method in &decl.methods {
+                    self.function(method);
+                }
+            }
+            StatementKind::Expr(expr) | StatementKind::Print(expr) => self.expr(expr),
+            StatementKind::Fn(decl) => {
+                self.declare(&decl.name, false);
+                self.function(decl);
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.condition(condition, "if");
+                self.expr(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementKind::Let(name, expr) => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+                self.declare(name, true);
+            }
+            StatementKind::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            StatementKind::While(condition, body) => {
+                // `while (true)` is how an endless loop is written, and
+                // what a `for` loop without a condition becomes.
+                if !matches!(condition.node, ExprKind::Literal(Literal::Boolean(true))) {
+                    self.condition(condition, "while");
+                }
+                self.expr(condition);
+                self.statement(body);
+            }
+        }
+    }
+
+    fn function(&mut self, decl: &FunctionDecl) {
+        self.begin_scope();
+        for param in &decl.params {
+            self.scopes
+                .last_mut()
+                .expect("a function always has a scope")
+                .insert(param.node, Binding { span: param.span.clone(), check_unused: false, used: false });
+        }
+        self.block(&decl.body);
+        self.end_scope();
+    }
+
+    fn condition(&mut self, condition: &Expr, keyword: &str) {
+        if is_constant(condition) {
+            let message = format!("This '{keyword}' condition is always the same.");
+            self.warn(Code::ConstantCondition, message, &condition.span);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.node {
+            ExprKind::Assign(_, value) => self.expr(value),
+            ExprKind::Binary(_, left, right) | ExprKind::Logical(_, left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Call(callee, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Get(object, _) => self.expr(object),
+            ExprKind::Grouping(expr) | ExprKind::Unary(_, expr) => self.expr(expr),
+            ExprKind::Literal(_) | ExprKind::Self_(_) | ExprKind::Super(_, _) => (),
+            ExprKind::Set(object, _, value) => {
+                self.expr(value);
+                self.expr(object);
+            }
+            ExprKind::Variable(variable) => self.read(variable.name),
+        }
+    }
+
+    fn read(&mut self, name: Identifier) {
+        if let Some(binding) = self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(&name)) {
+            binding.used = true;
+        }
+    }
+
+    fn declare(&mut self, name: &Spanned<Identifier>, is_let: bool) {
+        let (_, outer) = self.scopes.split_last().expect("the global scope is never popped");
+        let is_local = !outer.is_empty();
+
+        if outer.iter().any(|scope| scope.contains_key(&name.node)) {
+            let message = format!("'{name}' shadows a variable from an outer scope.");
+            self.warn(Code::ShadowedBinding, message, &name.span);
+        }
+
+        let binding = Binding { span: name.span.clone(), check_unused: is_let && is_local, used: false };
+        let scope = self.scopes.last_mut().expect("the global scope is never popped");
+        // The resolver rejects redeclaring a local, but the first binding
+        // can still be reported before it's replaced.
+        if let Some(previous) = scope.insert(name.node, binding) {
+            self.check_used(name.node, &previous);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("scopes are balanced");
+        for (name, binding) in scope {
+            self.check_used(name, &binding);
+        }
+    }
+
+    fn check_used(&mut self, name: Identifier, binding: &Binding) {
+        if binding.check_unused && !binding.used && !name.as_str().starts_with('_') {
+            let message = format!("Variable '{name}' is never read.");
+            self.warn(Code::UnusedBinding, message, &binding.span);
+        }
+    }
+
+    fn warn(&mut self, code: Code, message: String, span: &Span) {
+        self.warnings.push(Warning { code, message, span: span.clone() });
+    }
+}
+
+/// Whether control never reaches the statement after `stmt`.
+fn always_returns(stmt: &Statement) -> bool {
+    match &stmt.node {
+        StatementKind::Return(_) => true,
+        StatementKind::Block(stmts) => stmts.iter().any(always_returns),
+        StatementKind::If(_, then_branch, Some(else_branch)) => {
+            always_returns(then_branch) && always_returns(else_branch)
+        }
+        _ => false,
+    }
+}
+
+/// Whether `expr` is built only from literals, so it has the same value every time.
+fn is_constant(expr: &Expr) -> bool {
+    match &expr.node {
+        ExprKind::Literal(_) => true,
+        ExprKind::Grouping(expr) | ExprKind::Unary(_, expr) => is_constant(expr),
+        ExprKind::Binary(_, left, right) | ExprKind::Logical(_, left, right) => {
+            is_constant(left) && is_constant(right)
+        }
+        _ => false,
+    }
+}
+
+#[test]
+fn warns_about_suspicious_code() {
+    use crate::{parser::Parser, scanner::Scanner};
+
+    let source = "
+        let a = 1;
+        { let a = 2; print a; }
+        fn f() { let unused = 1; let _ignored = 2; return; print 3; }
+        if (1 < 2) print a;
+        while (true) f();
+    ";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let Ok(program) = Parser::new(tokens).parse() else {
+        panic!("expected a program");
+    };
+    let codes: Vec<_> = Linter::default().lint(&program).iter().map(|warning| warning.code).collect();
+
+    assert_eq!(
+        codes,
+        [Code::ShadowedBinding, Code::UnusedBinding, Code::UnreachableCode, Code::ConstantCondition]
+    );
+}
+
+#[test]
+fn reports_a_binding_replaced_in_the_same_scope() {
+    use crate::{parser::Parser, scanner::Scanner};
+
+    let source = "{ let a = 1; let a = 2; print a; }";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let Ok(program) = Parser::new(tokens).parse() else {
+        panic!("expected a program");
+    };
+    let warnings = Linter::default().lint(&program);
+
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, Code::UnusedBinding);
+    assert_eq!(warnings[0].span.offset(), source.find('a').unwrap());
+}
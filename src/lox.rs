@@ -1,39 +1,199 @@
 use std::{
     fs,
     io::{self, BufRead, Write},
+    path::Path,
     process::exit,
+    rc::Rc,
 };
 
 use console::{style, Term};
 
-use crate::{environment::Environment, error::LoxError, parser, scanner::Scanner};
+use crate::{
+    chunk::FunctionProto, code::Code, compiler::Compiler, diagnostic::{Diagnostic, Severity},
+    disassembler::Disassembler, environment::Environment, error::{Frame, LoxError, RuntimeError}, gc,
+    expr::eval, lint::Linter, loxc::CompiledProgram, parser, resolver::Resolver, scanner::Scanner, source_map::SourceMap,
+    statement::Statement, vm::Vm,
+};
+
+/// The name diagnostics use for code typed into the prompt.
+const PROMPT_PATH: &str = "<prompt>";
+
+/// How diagnostics are printed.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    /// Compiler-style snippets on stderr, for people.
+    #[default]
+    Human,
+    /// One JSON object per line on stderr, for editors and CI.
+    Json,
+}
+
+/// What runs the program once it has been checked.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// Evaluates the syntax tree directly.
+    #[default]
+    TreeWalker,
+    /// Compiles to bytecode and runs it on a stack machine.
+    Vm,
+}
 
 #[derive(Default)]
-pub struct Lox;
+pub struct Lox {
+    backend: Backend,
+    /// Used by the bytecode backend. It keeps its globals between runs, like `env` does.
+    vm: Vm,
+    error_format: ErrorFormat,
+    /// Report warnings as errors and don't run a program that has any.
+    deny_warnings: bool,
+    /// Print every instruction the bytecode backend executes.
+    trace_exec: bool,
+}
 
 impl Lox {
     pub fn entry_point(&mut self, args: &[String]) {
-        match args.len() {
-            1 => self.run_prompt(),
-            2 => self.run_file(&args[1]),
-            _ => {
-                println!("Usage: rlox [script]");
+        let mut rest = Vec::new();
+        for arg in &args[1..] {
+            if arg == "--deny-warnings" {
+                self.deny_warnings = true;
+                continue;
+            }
+            if arg == "--gc-stress" {
+                gc::set_stress(true);
+                continue;
+            }
+            if arg == "--trace-exec" {
+                self.trace_exec = true;
+                self.backend = Backend::Vm;
+                continue;
+            }
+            if let Some(backend) = arg.strip_prefix("--backend=") {
+                self.backend = match backend {
+                    "tree" => Backend::TreeWalker,
+                    "vm" => Backend::Vm,
+                    _ => self.usage(),
+                };
+                continue;
+            }
+            match arg.strip_prefix("--error-format=") {
+                Some("human") => self.error_format = ErrorFormat::Human,
+                Some("json") => self.error_format = ErrorFormat::Json,
+                Some(_) => self.usage(),
+                None => rest.push(arg.as_str()),
+            }
+        }
+
+        match rest.as_slice() {
+            [] => self.run_prompt(),
+            ["--explain", code] => self.explain(code),
+            ["disasm", path] => self.disassemble(path),
+            ["compile", input, "-o", output] => self.compile_file(input, output),
+            ["compile", input] => {
+                let output = Path::new(input).with_extension("loxc");
+                self.compile_file(input, &output.to_string_lossy())
+            }
+            [path] => self.run_file(path),
+            _ => self.usage(),
+        }
+    }
+
+    fn usage(&self) -> ! {
+        println!("Usage: rlox [--backend=tree|vm] [--trace-exec] [--gc-stress] [--error-format=human|json] [--deny-warnings] [script]");
+        println!("       rlox compile <script> [-o <output.loxc>]");
+        println!("       rlox disasm <script>");
+        println!("       rlox --explain <code>");
+        exit(64);
+    }
+
+    fn explain(&self, code: &str) {
+        match code.parse::<Code>() {
+            Ok(code) => print!("{}", code.explanation()),
+            Err(()) => {
+                println!("error: no error code named `{code}`");
                 exit(64);
             }
         }
     }
 
+    /// Prints the bytecode `path` compiles to, one script per top-level statement.
+    fn disassemble(&self, path: &str) {
+        let contents = self.read_source(path);
+        let source_map = SourceMap::new(path, &contents);
+        let Ok(scripts) = self.check(&contents, &source_map).and_then(|program| self.compile(&program, &source_map)) else {
+            exit(64);
+        };
+
+        let disassembler = Disassembler::new(&source_map);
+        for (i, script) in scripts.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            print!("{}", disassembler.function(script));
+        }
+    }
+
+    /// Compiles `input` to bytecode and saves it to `output`, to be run later
+    /// without scanning or parsing it again.
+    fn compile_file(&self, input: &str, output: &str) {
+        let contents = self.read_source(input);
+        let source_map = SourceMap::new(input, &contents);
+        let Ok(scripts) = self.check(&contents, &source_map).and_then(|program| self.compile(&program, &source_map)) else {
+            exit(64);
+        };
+
+        let program = CompiledProgram { path: input.to_string(), source: contents, scripts };
+        if let Err(err) = fs::write(output, program.encode()) {
+            self.report(Diagnostic::error(format!("couldn't write `{output}`: {err}")), &SourceMap::new(output, ""));
+            exit(64);
+        }
+    }
+
+    /// Runs a `.loxc` file made by `rlox compile` on the bytecode backend.
+    fn run_compiled(&mut self, path: &str) {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                self.report(Diagnostic::error(format!("couldn't read `{path}`: {err}")), &SourceMap::new(path, ""));
+                exit(64);
+            }
+        };
+        let program = match CompiledProgram::decode(&bytes) {
+            Ok(program) => program,
+            Err(err) => {
+                self.report((&err).into(), &SourceMap::new(path, ""));
+                exit(64);
+            }
+        };
+
+        let source_map = SourceMap::new(&program.path, &program.source);
+        self.execute(program.scripts, &source_map);
+    }
+
     fn run_file(&mut self, path: &str) {
-        let mut env = Environment::default();
-        let contents = fs::read_to_string(path).unwrap();
-        match self.run(&contents, &mut env) {
+        if path.ends_with(".loxc") {
+            return self.run_compiled(path);
+        }
+        let env = Environment::default();
+        let contents = self.read_source(path);
+        match self.run(path, &contents, &env) {
             Ok(_) => (),
             Err(_) => exit(64),
         }
     }
 
+    /// Reads the script at `path`, or reports why it can't and exits.
+    fn read_source(&self, path: &str) -> String {
+        match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                self.report(Diagnostic::error(format!("couldn't read `{path}`: {err}")), &SourceMap::new(path, ""));
+                exit(64);
+            }
+        }
+    }
+
     fn run_prompt(&mut self) {
-        let mut env = Environment::default();
+        let env = Environment::default();
 
         self.set_term_title("Lox");
         let stdin = io::stdin();
@@ -44,10 +204,8 @@ impl Lox {
                 Ok(ref cmd) if cmd == "clear" => self.clear(),
                 Ok(ref cmd) if cmd == "exit" => exit(0),
                 Ok(ref source) => {
-                    match self.run(source, &mut env) {
-                        Ok(_) => (),
-                        Err(err) => println!("{err}"),
-                    }
+                    // Errors have already been reported as diagnostics.
+                    let _ = self.run(PROMPT_PATH, source, &env);
                 },
                 _ => break,
             }
@@ -71,29 +229,167 @@ impl Lox {
         term.set_title(title)
     }
 
-    fn run(&mut self, source: &str, env: &mut Environment) -> Result<(), LoxError> {
-        let scanner = Scanner::new(source);
+    fn report(&self, diagnostic: Diagnostic, source_map: &SourceMap) {
+        match self.error_format {
+            ErrorFormat::Human => eprintln!("{}", diagnostic.render(source_map)),
+            ErrorFormat::Json => eprintln!("{}", diagnostic.render_json(source_map)),
+        }
+    }
 
-        match scanner.scan_tokens() {
-            Ok(tokens) => {
-                let mut parser = parser::Parser::new(tokens.to_vec());
-                let program = parser.parse()?;
-                for stmt in program {
-                    match stmt.eval(env) {
-                        Ok(_) => (),
-                        Err(type_err) => println!("ERROR: {}", style(type_err.to_string()).red()),
-                    }
-                }
-                
-                Ok(())
+    fn report_runtime_error(&self, err: &RuntimeError, backtrace: Vec<Frame>, source_map: &SourceMap) {
+        self.report(Self::runtime_diagnostic(err, &backtrace, source_map), source_map);
+    }
+
+    /// Describes an error that stopped a top-level statement, with the calls
+    /// it happened in, innermost first.
+    fn runtime_diagnostic(err: &RuntimeError, backtrace: &[Frame], source_map: &SourceMap) -> Diagnostic {
+        let mut diagnostic = Diagnostic::from(err);
+        let mut frames = backtrace.iter().rev().peekable();
+        while let Some(frame) = frames.next() {
+            let call_site = source_map.location(&frame.call_site);
+            diagnostic = diagnostic.with_note(format!("in `{}`, called at {call_site}", frame.function));
+
+            // Runaway recursion leaves hundreds of identical frames; show them once.
+            let mut repeated = 0;
+            while frames.next_if(|next| next.function == frame.function && next.call_site == frame.call_site).is_some() {
+                repeated += 1;
             }
+            if repeated > 0 {
+                diagnostic = diagnostic.with_note(format!("the frame above repeats {repeated} more times"));
+            }
+        }
+        diagnostic
+    }
+
+    /// Scans, parses, lints and resolves `source`, reporting every problem found.
+    fn check(&self, source: &str, source_map: &SourceMap) -> Result<Vec<Statement>, LoxError> {
+        let tokens = match Scanner::new(source).scan_tokens() {
+            Ok(tokens) => tokens,
             Err(errors) => {
                 for err in &errors {
-                    println!("{err}");
+                    self.report(err.into(), source_map);
+                }
+                return Err(LoxError::ScannerError(errors));
+            }
+        };
+
+        let mut parser = parser::Parser::new(tokens.to_vec());
+        let program = match parser.parse() {
+            Ok(program) => program,
+            Err(err) => {
+                for diagnostic in &err.diagnostics {
+                    self.report(diagnostic.into(), source_map);
                 }
+                return Err(err.into());
+            }
+        };
+
+        let warnings = Linter::default().lint(&program);
+        for warning in &warnings {
+            let mut diagnostic = Diagnostic::from(warning);
+            if self.deny_warnings {
+                diagnostic.severity = Severity::Error;
+            }
+            self.report(diagnostic, source_map);
+        }
 
-                Err(LoxError::ScannerError(errors))
+        // Resolve even when the warnings are denied, so its errors are reported too.
+        if let Err(errors) = Resolver::default().resolve(&program) {
+            for err in &errors {
+                self.report(err.into(), source_map);
             }
+            return Err(LoxError::ResolverError(errors));
         }
+        if self.deny_warnings && !warnings.is_empty() {
+            return Err(LoxError::Warnings(warnings));
+        }
+
+        Ok(program)
+    }
+
+    fn compile(&self, program: &[Statement], source_map: &SourceMap) -> Result<Vec<Rc<FunctionProto>>, LoxError> {
+        Compiler::default().compile(program).map_err(|errors| {
+            for err in &errors {
+                self.report(err.into(), source_map);
+            }
+            LoxError::CompileError(errors)
+        })
     }
+
+    fn run(&mut self, path: &str, source: &str, env: &Environment) -> Result<(), LoxError> {
+        let source_map = SourceMap::new(path, source);
+        let program = self.check(source, &source_map)?;
+
+        match self.backend {
+            Backend::TreeWalker => {
+                for stmt in &program {
+                    if let Err(err) = stmt.eval(env) {
+                        self.report_runtime_error(&err, eval::take_backtrace(), &source_map);
+                    }
+                }
+            }
+            Backend::Vm => {
+                let scripts = self.compile(&program, &source_map)?;
+                self.execute(scripts, &source_map);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs compiled scripts on the VM, going on with the next one after a runtime error.
+    fn execute(&mut self, scripts: Vec<Rc<FunctionProto>>, source_map: &SourceMap) {
+        for script in scripts {
+            let result = if self.trace_exec {
+                self.vm.run_traced(script, source_map)
+            } else {
+                self.vm.run(script)
+            };
+            if let Err(err) = result {
+                let backtrace = self.vm.take_backtrace();
+                self.report_runtime_error(&err, backtrace, source_map);
+            }
+        }
+    }
+}
+
+#[test]
+fn denied_warnings_still_resolve() {
+    let lox = Lox { deny_warnings: true, ..Default::default() };
+
+    // `b` is never used, and `return` is outside a function.
+    let source = "{ let b = 1; } return 2;";
+    let result = lox.check(source, &SourceMap::new("test.lox", source));
+    let Err(LoxError::ResolverError(errors)) = result else {
+        panic!("expected the resolver's error");
+    };
+    assert_eq!(errors[0].code, Code::TopLevelReturn);
+
+    let source = "{ let b = 1; }";
+    let result = lox.check(source, &SourceMap::new("test.lox", source));
+    assert!(matches!(result, Err(LoxError::Warnings(warnings)) if warnings.len() == 1));
+}
+
+#[test]
+fn renders_a_backtrace_with_repeated_frames_collapsed() {
+    use crate::statement::eval::run_source;
+
+    let source = "fn f(n) {\n  if (n == 0) return nil + 1;\n  return f(n - 1);\n}\nf(3);";
+    let Err(err) = run_source(source).1 else {
+        panic!("expected adding to nil to fail");
+    };
+    let source_map = SourceMap::new("test.lox", source);
+    let diagnostic = Lox::runtime_diagnostic(&err, &eval::take_backtrace(), &source_map);
+    assert_eq!(
+        diagnostic.notes,
+        [
+            "in `f`, called at test.lox:3:10",
+            "the frame above repeats 2 more times",
+            "in `f`, called at test.lox:5:1",
+        ]
+    );
+
+    let rendered = diagnostic.render(&source_map);
+    assert!(rendered.contains("note: in `f`, called at test.lox:3:10"));
+    assert!(rendered.contains("note: the frame above repeats 2 more times"));
 }
@@ -0,0 +1,521 @@
+//! The `.loxc` format, which stores compiled bytecode so a program can run
+//! without being scanned, parsed and compiled again.
+//!
+//! A file starts with the magic bytes `LOXC`, the format version as a
+//! `u16` and a checksum of the rest of the file as a `u64`. Then come the
+//! path and text of the source, so runtime errors can still point at it,
+//! and the script functions, one per top-level statement. Each function
+//! holds its name, arity, upvalue count, code, constant pool and line
+//! table. Integers are little-endian; operands inside the code keep the
+//! big-endian layout of the [`Chunk`].
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode, MAX_NESTING},
+    error::LoadError,
+    symbol::Symbol,
+    token::Span,
+};
+
+const MAGIC: &[u8; 4] = b"LOXC";
+
+/// Bumped whenever the layout of the file or the meaning of an opcode changes.
+pub const VERSION: u16 = 2;
+
+const HEADER_LEN: usize = MAGIC.len() + 2 + 8;
+
+/// A compiled program, as stored in a `.loxc` file.
+pub struct CompiledProgram {
+    pub path: String,
+    pub source: String,
+    pub scripts: Vec<Rc<FunctionProto>>,
+}
+
+impl CompiledProgram {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        write_str(&mut body, &self.path);
+        write_str(&mut body, &self.source);
+        write_u32(&mut body, self.scripts.len());
+        for script in &self.scripts {
+            write_function(&mut body, script);
+        }
+
+        let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&checksum(&body).to_le_bytes());
+        out.extend_from_slice(&body);
+        out
+    }
+
+    /// Reads a program written by [`CompiledProgram::encode`]. The checksum
+    /// only catches accidental damage, so every function is also verified
+    /// to keep the VM from indexing past its code, constants, stack or
+    /// upvalues. The VM still checks the types of values as it runs.
+    pub fn decode(bytes: &[u8]) -> Result<Self, LoadError> {
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(error("not a compiled Lox program"));
+        }
+        let mut reader = Reader { bytes, position: MAGIC.len(), depth: 0 };
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(error(format!(
+                "compiled with bytecode format version {version}, but this rlox reads version {VERSION}"
+            )));
+        }
+        let expected = reader.u64()?;
+        if checksum(&bytes[HEADER_LEN..]) != expected {
+            return Err(error("checksum mismatch: the file is corrupt"));
+        }
+
+        let path = reader.string()?;
+        let source = reader.string()?;
+        let count = reader.u32()?;
+        let mut scripts = Vec::new();
+        for _ in 0..count {
+            let script = reader.function()?;
+            verify(&script, None, source.len())?;
+            scripts.push(Rc::new(script));
+        }
+        if reader.position != bytes.len() {
+            return Err(error("unexpected data after the last function"));
+        }
+
+        Ok(Self { path, source, scripts })
+    }
+}
+
+fn write_function(out: &mut Vec<u8>, function: &FunctionProto) {
+    write_str(out, &function.name.as_str());
+    out.push(function.arity as u8);
+    out.extend_from_slice(&(function.upvalue_count as u16).to_le_bytes());
+
+    let chunk = &function.chunk;
+    write_u32(out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+
+    write_u32(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Number(n) => {
+                out.push(0);
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            Constant::String(s) => {
+                out.push(1);
+                write_str(out, s);
+            }
+            Constant::Function(nested) => {
+                out.push(2);
+                write_function(out, nested);
+            }
+            // Symbols only mean something to the process that interned them.
+            Constant::Name(name) => {
+                out.push(3);
+                write_str(out, &name.as_str());
+            }
+        }
+    }
+
+    // Consecutive bytes usually come from the same span, so store runs.
+    let mut runs: Vec<(usize, &Span)> = Vec::new();
+    for span in &chunk.spans {
+        match runs.last_mut() {
+            Some((count, last)) if last.offset() == span.offset() && last.len() == span.len() => *count += 1,
+            _ => runs.push((1, span)),
+        }
+    }
+    write_u32(out, runs.len());
+    for (count, span) in runs {
+        write_u32(out, count);
+        write_u32(out, span.offset());
+        write_u32(out, span.len());
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_u32(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// 64-bit FNV-1a, enough to catch truncated or damaged files.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn error(message: impl Into<String>) -> LoadError {
+    LoadError { message: message.into() }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// How many functions the one being read is nested in.
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let end = self.position.checked_add(len).filter(|&end| end <= self.bytes.len());
+        let Some(end) = end else {
+            return Err(error("unexpected end of file"));
+        };
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<usize, LoadError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let len = self.u32()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| error("invalid UTF-8 in a string"))
+    }
+
+    fn function(&mut self) -> Result<FunctionProto, LoadError> {
+        let name = Symbol::intern(&self.string()?);
+        let arity = self.u8()? as usize;
+        let upvalue_count = self.u16()? as usize;
+
+        let len = self.u32()?;
+        let code = self.take(len)?.to_vec();
+
+        let count = self.u32()?;
+        let mut constants = Vec::new();
+        for _ in 0..count {
+            let constant = match self.u8()? {
+                0 => Constant::Number(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+                1 => Constant::String(self.string()?.into()),
+                2 => {
+                    if self.depth == MAX_NESTING {
+                        return Err(error(format!("functions nested more than {MAX_NESTING} deep in `{name}`")));
+                    }
+                    self.depth += 1;
+                    let nested = self.function();
+                    self.depth -= 1;
+                    Constant::Function(Rc::new(nested?))
+                }
+                3 => Constant::Name(Symbol::intern(&self.string()?)),
+                tag => return Err(error(format!("unknown constant tag {tag} in `{name}`"))),
+            };
+            constants.push(constant);
+        }
+
+        let runs = self.u32()?;
+        let mut spans = Vec::new();
+        for _ in 0..runs {
+            let count = self.u32()?;
+            let span = Span::new(self.u32()?, self.u32()?);
+            if spans.len() + count > code.len() {
+                return Err(error(format!("the line table of `{name}` is longer than its code")));
+            }
+            spans.extend(std::iter::repeat_n(span, count));
+        }
+        if spans.len() != code.len() {
+            return Err(error(format!("the line table of `{name}` is shorter than its code")));
+        }
+
+        Ok(FunctionProto { name, arity, upvalue_count, chunk: Chunk { code, constants, spans } })
+    }
+}
+
+/// Checks that every instruction of `function` and of the functions nested
+/// in it is well formed: opcodes exist, operands are in bounds, jumps land
+/// on an instruction, and the code ends by returning. It then follows
+/// every path through the code, counting the values the function has on
+/// the stack, to check that no instruction pops or reads below its frame
+/// and that paths which meet agree on the count.
+///
+/// `enclosing_upvalues` is the number of upvalues of the function that
+/// creates `function`, or `None` for a script.
+fn verify(function: &FunctionProto, enclosing_upvalues: Option<usize>, source_len: usize) -> Result<(), LoadError> {
+    let chunk = &function.chunk;
+    let invalid = |offset: usize, what: &str| error(format!("{what} at offset {offset} in `{}`", function.name));
+
+    if function.upvalue_count > 0 && enclosing_upvalues.is_none() {
+        return Err(invalid(0, "a script with upvalues"));
+    }
+    if let Some(span) = chunk.spans.iter().find(|span| span.offset() + span.len() > source_len) {
+        return Err(invalid(span.offset(), "a span outside the source"));
+    }
+
+    let constant = |offset: usize| chunk.constants.get(chunk.read_u16(offset) as usize);
+    let is_name = |offset: usize| matches!(constant(offset), Some(Constant::Name(_)));
+
+    // Each instruction's opcode and the offset of the next one, by offset.
+    let mut instructions = HashMap::new();
+    let mut jumps = HashMap::new();
+    let mut last = None;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let start = offset;
+        let op = OpCode::try_from(chunk.code[offset]).map_err(|byte| invalid(offset, &format!("unknown opcode {byte}")))?;
+        let operands = match op {
+            OpCode::Constant
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method
+            | OpCode::Jump
+            | OpCode::JumpIfFalse
+            | OpCode::Loop
+            | OpCode::Closure => 2,
+            OpCode::Subclass => 4,
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Call => 1,
+            _ => 0,
+        };
+        if offset + operands >= chunk.code.len() {
+            return Err(invalid(offset, "a truncated instruction"));
+        }
+
+        let valid = match op {
+            OpCode::Constant => matches!(constant(offset + 1), Some(Constant::Number(_) | Constant::String(_))),
+            OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method => is_name(offset + 1),
+            OpCode::Subclass => is_name(offset + 1) && is_name(offset + 3),
+            OpCode::GetUpvalue | OpCode::SetUpvalue => (chunk.code[offset + 1] as usize) < function.upvalue_count,
+            OpCode::Jump | OpCode::JumpIfFalse => {
+                jumps.insert(offset, offset + 3 + chunk.read_u16(offset + 1) as usize);
+                true
+            }
+            OpCode::Loop => match (offset + 3).checked_sub(chunk.read_u16(offset + 1) as usize) {
+                Some(target) => {
+                    jumps.insert(offset, target);
+                    true
+                }
+                None => false,
+            },
+            _ => true,
+        };
+        if !valid {
+            return Err(invalid(offset, &format!("an invalid operand for {op:?}")));
+        }
+
+        offset += 1 + operands;
+        if op == OpCode::Closure {
+            let Some(Constant::Function(nested)) = constant(offset - 2) else {
+                return Err(invalid(offset - 3, "a closure over a constant that isn't a function"));
+            };
+            for _ in 0..nested.upvalue_count {
+                let (is_local, index) = match chunk.code.get(offset..offset + 2) {
+                    Some(&[is_local, index]) => (is_local, index as usize),
+                    _ => return Err(invalid(offset, "a truncated closure")),
+                };
+                let valid = match is_local {
+                    1 => true,
+                    0 => index < function.upvalue_count,
+                    _ => false,
+                };
+                if !valid {
+                    return Err(invalid(offset, "an invalid captured variable"));
+                }
+                offset += 2;
+            }
+        }
+        instructions.insert(start, (op, offset));
+        last = Some(op);
+    }
+
+    // Each nested function is verified once, however many closures make it.
+    // The reader has already limited how deeply they nest.
+    for constant in &chunk.constants {
+        if let Constant::Function(nested) = constant {
+            verify(nested, Some(function.upvalue_count), source_len)?;
+        }
+    }
+
+    if last != Some(OpCode::Return) {
+        return Err(invalid(chunk.code.len(), "code that doesn't end by returning"));
+    }
+    if let Some((offset, _)) = jumps.iter().find(|(_, target)| !instructions.contains_key(target)) {
+        return Err(invalid(*offset, "a jump into the middle of an instruction"));
+    }
+
+    // A call starts with the callee and its arguments on the stack.
+    let mut heights = HashMap::from([(0, 1 + function.arity)]);
+    let mut pending = vec![0];
+    while let Some(offset) = pending.pop() {
+        let height = heights[&offset];
+        let (op, next) = instructions[&offset];
+        let (pops, pushes) = match op {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetLocal
+            | OpCode::GetGlobal
+            | OpCode::GetUpvalue
+            | OpCode::Closure
+            | OpCode::Class => (0, 1),
+            OpCode::Jump | OpCode::Loop => (0, 0),
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print | OpCode::CloseUpvalue | OpCode::Return => (1, 0),
+            OpCode::SetLocal
+            | OpCode::SetGlobal
+            | OpCode::SetUpvalue
+            | OpCode::GetProperty
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::JumpIfFalse => (1, 1),
+            OpCode::Subclass => (1, 2),
+            OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Method => (2, 1),
+            OpCode::Call => (chunk.code[offset + 1] as usize + 1, 1),
+        };
+        if pops > height {
+            return Err(invalid(offset, "an instruction that pops below its frame"));
+        }
+
+        // Locals are read by slot, and captured by slot when a closure is made.
+        let slots = match op {
+            OpCode::GetLocal | OpCode::SetLocal => vec![chunk.code[offset + 1] as usize],
+            OpCode::Closure => chunk.code[offset + 3..next]
+                .chunks(2)
+                .filter(|capture| capture[0] == 1)
+                .map(|capture| capture[1] as usize)
+                .collect(),
+            _ => Vec::new(),
+        };
+        if slots.iter().any(|&slot| slot >= height) {
+            return Err(invalid(offset, "a local slot above the top of the stack"));
+        }
+
+        let successors = match op {
+            OpCode::Return => vec![],
+            OpCode::Jump | OpCode::Loop => vec![jumps[&offset]],
+            OpCode::JumpIfFalse => vec![next, jumps[&offset]],
+            _ => vec![next],
+        };
+        for successor in successors {
+            let height = height - pops + pushes;
+            match heights.insert(successor, height) {
+                None => pending.push(successor),
+                Some(previous) if previous != height => {
+                    return Err(invalid(successor, "paths that reach an instruction with different stack heights"));
+                }
+                Some(_) => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn round_trips_and_rejects_damaged_files() {
+    use crate::{
+        compiler::Compiler, parser::Parser, resolver::Resolver, scanner::Scanner, vm::{value::Value, Vm},
+    };
+
+    let source = "
+        class A { init(x) { self.x = x; } }
+        fn make(n) { fn get() { return A(n).x; } return get; }
+        let i = 0;
+        while (i < 3) { i = i + 1; }
+        let answer = make(i * 14)();
+    ";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Resolver::default().resolve(&program).unwrap();
+    let Ok(scripts) = Compiler::default().compile(&program) else {
+        panic!("expected the program to compile");
+    };
+    let compiled = CompiledProgram { path: "test.lox".into(), source: source.into(), scripts };
+    let bytes = compiled.encode();
+
+    let loaded = CompiledProgram::decode(&bytes).unwrap();
+    assert_eq!(loaded.path, "test.lox");
+    let mut vm = Vm::default();
+    for script in loaded.scripts {
+        assert!(vm.run(script).is_ok());
+    }
+    assert!(matches!(vm.global("answer"), Value::Number(answer) if answer == 42.));
+
+    for len in 0..bytes.len() {
+        assert!(CompiledProgram::decode(&bytes[..len]).is_err());
+    }
+    let mut damaged = bytes.clone();
+    damaged[HEADER_LEN + 20] ^= 0xff;
+    assert!(CompiledProgram::decode(&damaged).is_err());
+
+    let mut future = bytes.clone();
+    future[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    let err = CompiledProgram::decode(&future).err().unwrap();
+    assert!(err.message.contains("version"));
+
+    // Forged files have a matching checksum, so verification has to catch them.
+    let forge = |upvalue_count: usize, code: &[u8]| {
+        let mut script = FunctionProto { name: "script".into(), arity: 0, upvalue_count, chunk: Chunk::default() };
+        for &byte in code {
+            script.chunk.write(byte, &Span::default());
+        }
+        let forged = CompiledProgram { path: String::new(), source: String::new(), scripts: vec![Rc::new(script)] };
+        CompiledProgram::decode(&forged.encode()).err().expect("expected the forged file to be rejected").message
+    };
+    assert!(forge(0, &[OpCode::Nil as u8, 0xee]).contains("unknown opcode 238"));
+    assert!(forge(0, &[OpCode::GetLocal as u8, 5, OpCode::Return as u8]).contains("local slot above the top"));
+    assert!(forge(1, &[OpCode::GetUpvalue as u8, 0, OpCode::Return as u8]).contains("script with upvalues"));
+    assert!(forge(0, &[OpCode::Call as u8, 3, OpCode::Return as u8]).contains("pops below its frame"));
+    // A loop that leaves a value behind every time around.
+    let leak = [OpCode::Nil as u8, OpCode::Loop as u8, 0, 4, OpCode::Return as u8];
+    assert!(forge(0, &leak).contains("different stack heights"));
+
+    // Function constants nested in one another, each returning nil.
+    let nest = |depth: usize| {
+        let mut function = None;
+        for _ in 0..=depth {
+            let mut chunk = Chunk::default();
+            chunk.write(OpCode::Nil as u8, &Span::default());
+            chunk.write(OpCode::Return as u8, &Span::default());
+            chunk.constants.extend(function.map(Constant::Function));
+            function = Some(Rc::new(FunctionProto { name: "f".into(), arity: 0, upvalue_count: 0, chunk }));
+        }
+        let scripts = function.into_iter().collect();
+        CompiledProgram { path: String::new(), source: String::new(), scripts }.encode()
+    };
+    assert!(CompiledProgram::decode(&nest(MAX_NESTING)).is_ok());
+    let err = CompiledProgram::decode(&nest(MAX_NESTING + 1)).err().expect("expected the nesting to be rejected");
+    assert!(err.message.contains("nested more than 256 deep"));
+}
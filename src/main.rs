@@ -1,9 +1,18 @@
-use std::env;
+use std::{env, thread};
 
-use rlox::lox::Lox;
+use rlox::{expr::eval::STACK_SIZE, lox::Lox};
 
 fn main() {
     let args: Vec<_> = env::args().collect();
-    let mut lox = Lox::default();
-    lox.entry_point(&args);
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut lox = Lox::default();
+            lox.entry_point(&args);
+        })
+        .expect("failed to start the interpreter thread");
+
+    if interpreter.join().is_err() {
+        std::process::exit(101);
+    }
 }
@@ -0,0 +1,104 @@
+//! The functions implemented in Rust that every Lox program can call.
+//! They are defined once, over [`NativeValue`], and each backend turns
+//! them into its own values, so the backends can't disagree about them.
+
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{error::RuntimeError, gc};
+
+/// The values a native function can take and return, as a backend represents them.
+pub trait NativeValue: Sized {
+    fn number(n: f64) -> Self;
+
+    /// A new instance of a class named `class`, with numeric fields.
+    fn record(class: &str, fields: &[(&str, f64)]) -> Self;
+}
+
+/// A function implemented in Rust and exposed to Lox programs as a global.
+pub struct NativeFunction<V> {
+    pub name: &'static str,
+    pub arity: usize,
+    pub fun: fn(&[V]) -> V,
+}
+
+// Derived impls would require `V: Clone`, but only the function pointer is copied.
+impl<V> Clone for NativeFunction<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V> Copy for NativeFunction<V> {}
+
+impl<V> NativeFunction<V> {
+    pub fn call(&self, args: Vec<V>) -> Result<V, RuntimeError> {
+        Ok((self.fun)(&args))
+    }
+}
+
+impl<V> Display for NativeFunction<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+/// Every native function, to be defined as globals.
+pub fn natives<V: NativeValue>() -> [NativeFunction<V>; 2] {
+    [
+        NativeFunction {
+            name: "clock",
+            arity: 0,
+            fun: clock,
+        },
+        NativeFunction {
+            name: "gc_stats",
+            arity: 0,
+            fun: gc_stats,
+        },
+    ]
+}
+
+fn clock<V: NativeValue>(_args: &[V]) -> V {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    V::number(now.as_secs_f64())
+}
+
+/// Returns an instance with the `collections`, `collected` and `live`
+/// counts of the garbage collector.
+fn gc_stats<V: NativeValue>(_args: &[V]) -> V {
+    let stats = gc::stats();
+    V::record(
+        "GcStats",
+        &[
+            ("collections", stats.collections as f64),
+            ("collected", stats.collected as f64),
+            ("live", stats.live as f64),
+        ],
+    )
+}
+
+#[test]
+fn both_backends_define_the_same_natives() {
+    use crate::{expr::eval, statement::eval::run_source, vm::{self, value}};
+
+    let source = "
+        let stats = gc_stats();
+        let counts = stats.collections + stats.collected + stats.live;
+        let ticking = clock() > 0;
+    ";
+
+    let (env, result) = run_source(source);
+    assert!(result.is_ok());
+    assert!(matches!(env.global("counts"), eval::Value::Number(_)));
+    assert!(matches!(env.global("ticking"), eval::Value::Boolean(true)));
+
+    let (vm, result) = vm::run_source(source);
+    assert!(result.is_ok());
+    assert!(matches!(vm.global("counts"), value::Value::Number(_)));
+    assert!(matches!(vm.global("ticking"), value::Value::Boolean(true)));
+}
@@ -1,177 +1,467 @@
 use crate::{
-    error::ParserError, expr::{Expr, Literal}, statement::Statement, token::Token, token_kind::TokenKind
+    code::Code, error::{ParserError, SyntaxError}, expr::{Expr, ExprKind, Literal, Variable}, statement::{ClassDecl, FunctionDecl, Statement, StatementKind}, token::{Span, Spanned, Token}, token_kind::TokenKind, Identifier
 };
 
+/// Calls and declarations can't have more arguments or parameters than this.
+const MAX_ARITY: usize = 255;
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    diagnostics: Vec<SyntaxError>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self { tokens, current: 0, diagnostics: Vec::new() }
     }
 
+    /// Parses the whole program, recovering after each syntax error so that
+    /// all of them are reported at once. Printing them is up to the caller.
     pub fn parse(&mut self) -> Result<Vec<Statement>, ParserError> {
         let mut statements = Vec::new();
-        let mut error = false;
 
         while !self.eof() {
-            if let Ok(stmt) = self.statement() {
+            if let Some(stmt) = self.recovering_declaration() {
                 statements.push(stmt);
-            } else {
-                self.synchronize();
-                error = true;
             }
         }
 
-        if error { Err(ParserError) } else { Ok(statements) }
+        if self.diagnostics.is_empty() {
+            Ok(statements)
+        } else {
+            Err(ParserError { diagnostics: std::mem::take(&mut self.diagnostics) })
+        }
     }
 
-    fn statement(&mut self) -> Result<Statement, ParserError> {
-        if self.match_(&[TokenKind::Print]) {            
-            self.print_statement()
+    /// Parses a declaration. On a syntax error, records it and skips to
+    /// where the next declaration probably starts, so that parsing can go
+    /// on at the same nesting level.
+    fn recovering_declaration(&mut self) -> Option<Statement> {
+        let start = self.current;
+        match self.declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(err) => {
+                self.diagnostics.push(err);
+                self.synchronize(start);
+                None
+            }
+        }
+    }
+
+    /// Declarations can only appear where they are in scope for the rest of
+    /// a block, so they aren't allowed as the body of an `if` or a loop.
+    fn declaration(&mut self) -> Result<Statement, SyntaxError> {
+        let start = self.peek().span.clone();
+        let kind = if self.match_(&[TokenKind::Class]) {
+            self.class_declaration()?
+        } else if self.match_(&[TokenKind::Fn]) {
+            StatementKind::Fn(self.function("function")?.into())
         } else if self.match_(&[TokenKind::Let]) {
-            self.binding_statement()
+            self.binding_statement()?
+        } else {
+            return self.statement();
+        };
+        Ok(Statement::new(kind, self.span_from(&start)))
+    }
+
+    fn statement(&mut self) -> Result<Statement, SyntaxError> {
+        let start = self.peek().span.clone();
+        let kind = self.statement_kind()?;
+        Ok(Statement::new(kind, self.span_from(&start)))
+    }
+
+    fn statement_kind(&mut self) -> Result<StatementKind, SyntaxError> {
+        if self.match_(&[TokenKind::Print]) {
+            self.print_statement()
         } else if self.match_(&[TokenKind::LeftBrace]) {
             self.block_statement()
+        } else if self.match_(&[TokenKind::If]) {
+            self.if_statement()
+        } else if self.match_(&[TokenKind::While]) {
+            self.while_statement()
+        } else if self.match_(&[TokenKind::For]) {
+            self.for_statement()
+        } else if self.match_(&[TokenKind::Return]) {
+            self.return_statement()
         } else {
             self.expression_statement()
         }
     }
 
-    fn print_statement(&mut self) -> Result<Statement, ParserError> {
+    fn print_statement(&mut self) -> Result<StatementKind, SyntaxError> {
         let expr = self.expression()?;
         self.consume(TokenKind::Semicolon, "Expected ';' after expression.")?;
-        Ok(Statement::Print(expr))
+        Ok(StatementKind::Print(expr))
     }
 
-    fn binding_statement(&mut self) -> Result<Statement, ParserError> {
-        // Can't mut borrow twice, so we clone the value.
-        let name = self
-            .consume(TokenKind::Identifier("".into()), "Expected variable name.")
-            .cloned();
+    fn binding_statement(&mut self) -> Result<StatementKind, SyntaxError> {
+        let name = identifier(self.consume(TokenKind::Identifier("".into()), "Expected variable name.")?);
 
-        if self.match_(&[TokenKind::Equal]) {
-            let value = self.expression();
-            // We want to consume the semicolon before emiting any errors to match the book's logic.
-            self.consume(TokenKind::Semicolon, "Expected ';' after let binding.")?;
-            let name = name?;
-            let value = value?;
-            Ok(Statement::Let(name.lexeme, value))
+        let value = if self.match_(&[TokenKind::Equal]) {
+            Some(self.expression()?)
         } else {
-            self.consume(TokenKind::Semicolon, "Expected ';' after let binding.")?;
-            Err(ParserError)
-        }
+            None
+        };
+
+        self.consume(TokenKind::Semicolon, "Expected ';' after let binding.")?;
+        Ok(StatementKind::Let(name, value))
     }
 
-    fn expression_statement(&mut self) -> Result<Statement, ParserError> {
+    fn expression_statement(&mut self) -> Result<StatementKind, SyntaxError> {
         let expr = self.expression()?;
         self.consume(TokenKind::Semicolon, "Expected ';' after expression.")?;
-        Ok(Statement::Expr(expr))
+        Ok(StatementKind::Expr(expr))
     }
 
-    fn block_statement(&mut self) -> Result<Statement, ParserError> {
+    fn block_statement(&mut self) -> Result<StatementKind, SyntaxError> {
+        Ok(StatementKind::Block(self.block()?))
+    }
+
+    /// Parses the statements of a block whose '{' was already consumed.
+    fn block(&mut self) -> Result<Vec<Statement>, SyntaxError> {
         let mut stmts = Vec::new();
 
         while !self.check(&TokenKind::RightBrace) && !self.eof() {
-            stmts.push(self.statement()?);
+            stmts.extend(self.recovering_declaration());
         }
 
         self.consume(TokenKind::RightBrace, "Expected '}' after block.")?;
-        Ok(Statement::Block(stmts))
+        Ok(stmts)
+    }
+
+    fn class_declaration(&mut self) -> Result<StatementKind, SyntaxError> {
+        let name = identifier(self.consume(TokenKind::Identifier("".into()), "Expected class name.")?);
+
+        let superclass = if self.match_(&[TokenKind::Less]) {
+            let superclass = identifier(self.consume(TokenKind::Identifier("".into()), "Expected superclass name.")?);
+            Some(Variable::new(superclass.node, superclass.span))
+        } else {
+            None
+        };
+
+        self.consume(TokenKind::LeftBrace, "Expected '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(&TokenKind::RightBrace) && !self.eof() {
+            methods.push(self.function("method")?.into());
+        }
+
+        self.consume(TokenKind::RightBrace, "Expected '}' after class body.")?;
+        Ok(StatementKind::Class(ClassDecl { name, superclass, methods }))
+    }
+
+    /// Parses a function's name, parameters and body. `kind` is used in error messages.
+    fn function(&mut self, kind: &str) -> Result<FunctionDecl, SyntaxError> {
+        let name = identifier(self.consume(TokenKind::Identifier("".into()), &format!("Expected {kind} name."))?);
+
+        self.consume(TokenKind::LeftParen, &format!("Expected '(' after {kind} name."))?;
+        let mut params = Vec::new();
+        if !self.check(&TokenKind::RightParen) {
+            loop {
+                if params.len() >= MAX_ARITY {
+                    return self
+                        .error(self.peek(), Code::TooManyArguments, "Can't have more than 255 parameters.")
+                        .map(|_| unreachable!());
+                }
+                let param = self.consume(TokenKind::Identifier("".into()), "Expected parameter name.")?;
+                params.push(identifier(param));
+
+                if !self.match_(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenKind::RightParen, "Expected ')' after parameters.")?;
+
+        self.consume(TokenKind::LeftBrace, &format!("Expected '{{' before {kind} body."))?;
+        let body = self.block()?;
+
+        Ok(FunctionDecl { name, params, body })
+    }
+
+    fn return_statement(&mut self) -> Result<StatementKind, SyntaxError> {
+        let value = if self.check(&TokenKind::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(TokenKind::Semicolon, "Expected ';' after return value.")?;
+        Ok(StatementKind::Return(value))
+    }
+
+    fn if_statement(&mut self) -> Result<StatementKind, SyntaxError> {
+        self.consume(TokenKind::LeftParen, "Expected '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expected ')' after if condition.")?;
+
+        let then_branch = self.statement()?;
+        let else_branch = if self.match_(&[TokenKind::Else]) {
+            Some(self.statement()?.into())
+        } else {
+            None
+        };
+
+        Ok(StatementKind::If(condition, then_branch.into(), else_branch))
+    }
+
+    fn while_statement(&mut self) -> Result<StatementKind, SyntaxError> {
+        self.consume(TokenKind::LeftParen, "Expected '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expected ')' after while condition.")?;
+        let body = self.statement()?;
+
+        Ok(StatementKind::While(condition, body.into()))
+    }
+
+    /// Desugars `for (init; cond; incr) body` into
+    /// `{ init; while (cond) { body; incr; } }`, so the loop
+    /// binding is scoped to the enclosing block.
+    fn for_statement(&mut self) -> Result<StatementKind, SyntaxError> {
+        let start = self.previous().span.clone();
+        self.consume(TokenKind::LeftParen, "Expected '(' after 'for'.")?;
+
+        let initializer = if self.match_(&[TokenKind::Semicolon]) {
+            None
+        } else {
+            let start = self.peek().span.clone();
+            let kind = if self.match_(&[TokenKind::Let]) {
+                self.binding_statement()?
+            } else {
+                self.expression_statement()?
+            };
+            Some(Statement::new(kind, self.span_from(&start)))
+        };
+
+        let condition = if self.check(&TokenKind::Semicolon) {
+            Expr::new(ExprKind::Literal(Literal::Boolean(true)), self.peek().span.clone())
+        } else {
+            self.expression()?
+        };
+        self.consume(TokenKind::Semicolon, "Expected ';' after loop condition.")?;
+
+        let increment = if self.check(&TokenKind::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenKind::RightParen, "Expected ')' after for clauses.")?;
+
+        let mut body = self.statement()?;
+        // The synthesized nodes all cover the whole `for` statement.
+        let span = self.span_from(&start);
+
+        if let Some(increment) = increment {
+            let increment = Statement::new(StatementKind::Expr(increment), span.clone());
+            body = Statement::new(StatementKind::Block(vec![body, increment]), span.clone());
+        }
+
+        let body = StatementKind::While(condition, body.into());
+
+        match initializer {
+            Some(initializer) => {
+                let body = Statement::new(body, span);
+                Ok(StatementKind::Block(vec![initializer, body]))
+            }
+            None => Ok(body),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, SyntaxError> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr, SyntaxError> {
+        let expr = self.logic_or()?;
+
+        if self.match_(&[TokenKind::Equal]) {
+            let equals = self.previous().clone();
+            // Assignment is right-associative, so we recurse instead of looping.
+            let value = self.assignment()?;
+
+            let span = expr.span.merge(&value.span);
+            return match expr.node {
+                ExprKind::Variable(variable) => Ok(Expr::new(ExprKind::Assign(variable, value.into()), span)),
+                ExprKind::Get(object, name) => Ok(Expr::new(ExprKind::Set(object, name, value.into()), span)),
+                _ => self
+                    .error(&equals, Code::InvalidAssignmentTarget, "Invalid assignment target.")
+                    .map(|_| unreachable!()),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn logic_or(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.logic_and()?;
+
+        while self.match_(&[TokenKind::Or]) {
+            let operator = (&self.previous().kind).into();
+            let right = self.logic_and()?;
+            let span = expr.span.merge(&right.span);
+            expr = Expr::new(ExprKind::Logical(operator, expr.into(), right.into()), span);
+        }
+
+        Ok(expr)
     }
 
-    fn expression(&mut self) -> Result<Expr, ParserError> {
-        self.equality()
+    fn logic_and(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.equality()?;
+
+        while self.match_(&[TokenKind::And]) {
+            let operator = (&self.previous().kind).into();
+            let right = self.equality()?;
+            let span = expr.span.merge(&right.span);
+            expr = Expr::new(ExprKind::Logical(operator, expr.into(), right.into()), span);
+        }
+
+        Ok(expr)
     }
 
-    fn equality(&mut self) -> Result<Expr, ParserError> {
+    fn equality(&mut self) -> Result<Expr, SyntaxError> {
         let mut expr = self.comparison()?;
 
         use TokenKind::*;
         while self.match_(&[BangEqual, EqualEqual]) {
             let operator = (&self.previous().kind).into();
             let right = self.comparison()?;
-            expr = Expr::Binary(operator, expr.into(), right.into());
+            let span = expr.span.merge(&right.span);
+            expr = Expr::new(ExprKind::Binary(operator, expr.into(), right.into()), span);
         }
 
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr, ParserError> {
+    fn comparison(&mut self) -> Result<Expr, SyntaxError> {
         let mut expr = self.term()?;
 
         use TokenKind::*;
         while self.match_(&[Greater, GreaterEqual, Less, LessEqual]) {
             let operator = (&self.previous().kind).into();
             let right = self.term()?;
-            expr = Expr::Binary(operator, expr.into(), right.into());
+            let span = expr.span.merge(&right.span);
+            expr = Expr::new(ExprKind::Binary(operator, expr.into(), right.into()), span);
         }
 
         Ok(expr)
     }
 
-    fn term(&mut self) -> Result<Expr, ParserError> {
+    fn term(&mut self) -> Result<Expr, SyntaxError> {
         let mut expr = self.factor()?;
 
         use TokenKind::*;
         while self.match_(&[Minus, Plus]) {
             let operator = (&self.previous().kind).into();
             let right = self.factor()?;
-            expr = Expr::Binary(operator, expr.into(), right.into());
+            let span = expr.span.merge(&right.span);
+            expr = Expr::new(ExprKind::Binary(operator, expr.into(), right.into()), span);
         }
 
         Ok(expr)
     }
 
-    fn factor(&mut self) -> Result<Expr, ParserError> {
+    fn factor(&mut self) -> Result<Expr, SyntaxError> {
         let mut expr = self.unary()?;
 
         use TokenKind::*;
         while self.match_(&[Slash, Star]) {
             let operator = (&self.previous().kind).into();
             let right = self.unary()?;
-            expr = Expr::Binary(operator, expr.into(), right.into());
+            let span = expr.span.merge(&right.span);
+            expr = Expr::new(ExprKind::Binary(operator, expr.into(), right.into()), span);
         }
 
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Expr, ParserError> {
+    fn unary(&mut self) -> Result<Expr, SyntaxError> {
         use TokenKind::*;
         if self.match_(&[Bang, Minus]) {
+            let start = self.previous().span.clone();
             let operator = (&self.previous().kind).into();
             let right = self.unary()?;
-            return Ok(Expr::Unary(operator, right.into()));
+            let span = start.merge(&right.span);
+            return Ok(Expr::new(ExprKind::Unary(operator, right.into()), span));
         }
 
-        self.primary()
+        self.call()
     }
 
-    fn primary(&mut self) -> Result<Expr, ParserError> {
+    fn call(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_(&[TokenKind::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_(&[TokenKind::Dot]) {
+                let name = self.consume(TokenKind::Identifier("".into()), "Expected property name after '.'.")?;
+                let name = identifier(name);
+                let span = expr.span.merge(&name.span);
+                expr = Expr::new(ExprKind::Get(expr.into(), name), span);
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, SyntaxError> {
+        let mut args = Vec::new();
+        if !self.check(&TokenKind::RightParen) {
+            loop {
+                if args.len() >= MAX_ARITY {
+                    return self
+                        .error(self.peek(), Code::TooManyArguments, "Can't have more than 255 arguments.")
+                        .map(|_| unreachable!());
+                }
+                args.push(self.expression()?);
+
+                if !self.match_(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenKind::RightParen, "Expected ')' after arguments.")?;
+        let span = self.span_from(&callee.span);
+        Ok(Expr::new(ExprKind::Call(callee.into(), args), span))
+    }
+
+    fn primary(&mut self) -> Result<Expr, SyntaxError> {
         use TokenKind::*;
+        let span = self.peek().span.clone();
         let expr = match &self.peek().kind {
-            False => Expr::Literal(Literal::Boolean(false)),
-            True => Expr::Literal(Literal::Boolean(true)),
-            Nil => Expr::Literal(Literal::Nil),
-            Number(n) => Expr::Literal(Literal::Number(n.to_owned())),
-            String(s) => Expr::Literal(Literal::String(s.to_owned())),
-            Identifier(n) => Expr::Variable(n.to_owned()),
+            False => ExprKind::Literal(Literal::Boolean(false)),
+            True => ExprKind::Literal(Literal::Boolean(true)),
+            Nil => ExprKind::Literal(Literal::Nil),
+            Self_ => ExprKind::Self_(Variable::new("self".into(), span.clone())),
+            Number(n) => ExprKind::Literal(Literal::Number(n.to_owned())),
+            String(s) => ExprKind::Literal(Literal::String(s.as_str().into())),
+            Identifier(n) => ExprKind::Variable(Variable::new(*n, span.clone())),
             LeftParen => {
+                self.advance();
                 let expr = self.expression()?;
                 self.consume(RightParen, "Expected ')' after expression.")?;
-                Expr::Grouping(expr.into())
+                return Ok(Expr::new(ExprKind::Grouping(expr.into()), self.span_from(&span)));
+            }
+            Super => {
+                self.advance();
+                self.consume(Dot, "Expected '.' after 'super'.")?;
+                let method = identifier(self.consume(Identifier("".into()), "Expected superclass method name.")?);
+                let variable = Variable::new("super".into(), span.clone());
+                return Ok(Expr::new(ExprKind::Super(variable, method), self.span_from(&span)));
             }
             _ => {
                 return self
-                    .error(self.peek(), "Expected expression.")
+                    .error(self.peek(), Code::ExpectedExpression, "Expected expression.")
                     .map(|_| unreachable!());
             }
         };
 
         self.current += 1;
-        Ok(expr)
+        Ok(Expr::new(expr, span))
     }
 
     fn match_(&mut self, token_kinds: &[TokenKind]) -> bool {
@@ -184,12 +474,12 @@ impl Parser {
         false
     }
 
-    fn consume(&mut self, until: TokenKind, error_message: &str) -> Result<&Token, ParserError> {
+    fn consume(&mut self, until: TokenKind, error_message: &str) -> Result<&Token, SyntaxError> {
         if self.check(&until) {
             return Ok(self.advance());
         }
 
-        self.error(self.peek(), error_message)
+        self.error(self.peek(), Code::ExpectedToken, error_message)
             .map(|_| unreachable!())
     }
 
@@ -220,20 +510,33 @@ impl Parser {
         &self.tokens[self.current - 1]
     }
 
-    fn error(&self, token: &Token, message: &str) -> Result<Void, ParserError> {
-        // TODO: push error to Lox.errors
-        println!("{token} : {message}");
-        Err(ParserError)
+    /// Returns the span from `start` up to the end of the last consumed token.
+    fn span_from(&self, start: &Span) -> Span {
+        start.merge(&self.previous().span)
     }
 
-    fn synchronize(&mut self) {
-        self.advance();
+    fn error(&self, token: &Token, code: Code, message: &str) -> Result<Void, SyntaxError> {
+        Err(SyntaxError {
+            code,
+            message: message.to_string(),
+            token: token.clone(),
+            span: token.span.clone(),
+        })
+    }
+
+    /// Skips tokens until the end of the statement that started at token
+    /// `start`, or until a token that starts a statement or ends a block.
+    /// Always skips at least one token, so recovery can't loop forever.
+    fn synchronize(&mut self, start: usize) {
+        if self.current == start {
+            self.advance();
+        }
 
         use TokenKind::*;
         while !self.eof() {
             if matches!(self.previous().kind, Semicolon) { return; }
             match self.peek().kind {
-                Class | Fn | Let | For | If | While | Print | Return => return,
+                Class | Fn | Let | For | If | While | Print | Return | RightBrace => return,
                 _ => (),
             }
             self.advance();
@@ -241,5 +544,101 @@ impl Parser {
     }
 }
 
+/// The name an identifier token spells, with where it was written.
+fn identifier(token: &Token) -> Spanned<Identifier> {
+    let TokenKind::Identifier(name) = token.kind else {
+        unreachable!("only called with tokens consumed as identifiers");
+    };
+    Spanned::new(name, token.span.clone())
+}
+
 #[allow(dead_code)]
 enum Void {}
+
+#[test]
+fn collects_every_syntax_error() {
+    use crate::scanner::Scanner;
+
+    let tokens = Scanner::new("print 1 +; print (2; print 3;").scan_tokens().unwrap();
+    let Err(err) = Parser::new(tokens).parse() else {
+        panic!("expected syntax errors");
+    };
+
+    let messages: Vec<_> = err.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, ["Expected expression.", "Expected ')' after expression."]);
+}
+
+#[test]
+fn spans_cover_whole_expressions() {
+    use crate::scanner::Scanner;
+
+    let tokens = Scanner::new("print (1 + 2) * 3;").scan_tokens().unwrap();
+    let Ok(program) = Parser::new(tokens).parse() else {
+        panic!("expected a program");
+    };
+    let StatementKind::Print(expr) = &program[0].node else {
+        panic!("expected a print statement");
+    };
+
+    assert_eq!((program[0].span.offset(), program[0].span.len()), (0, 18));
+    assert_eq!((expr.span.offset(), expr.span.len()), (6, 11));
+    let ExprKind::Binary(_, grouping, _) = &expr.node else {
+        panic!("expected a binary expression");
+    };
+    assert_eq!((grouping.span.offset(), grouping.span.len()), (6, 7));
+}
+
+#[test]
+fn recovers_inside_nested_blocks() {
+    use crate::scanner::Scanner;
+
+    let source = "
+        fn f() {
+            let = 1;
+            if (true) { print (2; }
+            let ok;
+        }
+        print 3 +;
+        let x;
+    ";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let Err(err) = Parser::new(tokens).parse() else {
+        panic!("expected syntax errors");
+    };
+
+    let messages: Vec<_> = err.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, ["Expected variable name.", "Expected ')' after expression.", "Expected expression."]);
+}
+
+#[test]
+fn desugars_for_into_while() {
+    use crate::scanner::Scanner;
+
+    let tokens = Scanner::new("for (let i = 0; i < 3; i = i + 1) print i; for (;;) {}").scan_tokens().unwrap();
+    let Ok(program) = Parser::new(tokens).parse() else {
+        panic!("expected a program");
+    };
+
+    // `{ let i = 0; while (i < 3) { print i; i = i + 1; } }`
+    let StatementKind::Block(stmts) = &program[0].node else {
+        panic!("expected the initializer and loop in a block");
+    };
+    let [initializer, looped] = &stmts[..] else {
+        panic!("expected two statements");
+    };
+    assert!(matches!(initializer.node, StatementKind::Let(..)));
+    let StatementKind::While(condition, body) = &looped.node else {
+        panic!("expected a while loop");
+    };
+    assert_eq!(condition.to_string(), "(< (i) 3)");
+    let StatementKind::Block(body) = &body.node else {
+        panic!("expected the body and increment in a block");
+    };
+    assert!(matches!(body[..], [Statement { node: StatementKind::Print(_), .. }, Statement { node: StatementKind::Expr(_), .. }]));
+
+    // Without clauses, there is no block and the condition is `true`.
+    let StatementKind::While(condition, _) = &program[1].node else {
+        panic!("expected a bare while loop");
+    };
+    assert_eq!(condition.to_string(), "true");
+}
@@ -0,0 +1,291 @@
+use std::{collections::HashMap, mem};
+
+use crate::{
+    code::Code,
+    error::ResolverError,
+    expr::{Expr, ExprKind, Slot, Variable},
+    statement::{FunctionDecl, Statement, StatementKind},
+    token::{Span, Spanned},
+    Identifier,
+};
+
+/// A static pass that runs between parsing and evaluation. It gives
+/// every local variable a [`Slot`], so the interpreter doesn't search
+/// scopes by name, and reports scope mistakes before the program runs.
+#[derive(Default)]
+pub struct Resolver {
+    scopes: Vec<HashMap<Identifier, Local>>,
+    function: FunctionKind,
+    class: ClassKind,
+    errors: Vec<ResolverError>,
+}
+
+struct Local {
+    index: usize,
+    /// `false` while the variable's initializer is being resolved.
+    defined: bool,
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    #[default]
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum ClassKind {
+    #[default]
+    None,
+    Class,
+    Subclass,
+}
+
+impl Resolver {
+    pub fn resolve(mut self, program: &[Statement]) -> Result<(), Vec<ResolverError>> {
+        self.resolve_all(program);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn resolve_all(&mut self, stmts: &[Statement]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match &stmt.node {
+            StatementKind::Block(stmts) => {
+                self.begin_scope();
+                self.resolve_all(stmts);
+                self.end_scope();
+            }
+            StatementKind::Class(decl) => {
+                let enclosing_class = mem::replace(&mut self.class, ClassKind::Class);
+                self.declare(&decl.name);
+                self.define(&decl.name);
+
+                if let Some(superclass) = &decl.superclass {
+                    if superclass.name == *decl.name {
+                        self.error(Code::InheritsFromItself, "A class can't inherit from itself.", &superclass.span);
+                    }
+                    self.class = ClassKind::Subclass;
+                    self.variable(superclass);
+                    self.begin_scope();
+                    self.define(&Spanned::new("super".into(), superclass.span.clone()));
+                }
+
+                self.begin_scope();
+                self.define(&Spanned::new("self".into(), decl.name.span.clone()));
+                for method in &decl.methods {
+                    let kind = if *method.name == "init" {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    self.function(method, kind);
+                }
+                self.end_scope();
+
+                if decl.superclass.is_some() {
+                    self.end_scope();
+                }
+                self.class = enclosing_class;
+            }
+            StatementKind::Expr(expr) | StatementKind::Print(expr) => self.expr(expr),
+            StatementKind::Fn(decl) => {
+                // Define the name eagerly so the function can refer to itself.
+                self.declare(&decl.name);
+                self.define(&decl.name);
+                self.function(decl, FunctionKind::Function);
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementKind::Let(name, expr) => {
+                self.declare(name);
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+                self.define(name);
+            }
+            StatementKind::Return(expr) => {
+                if self.function == FunctionKind::None {
+                    self.error(Code::TopLevelReturn, "Can't return from top-level code.", &stmt.span);
+                }
+                if let Some(expr) = expr {
+                    if self.function == FunctionKind::Initializer {
+                        self.error(Code::ReturnFromInitializer, "Can't return a value from an initializer.", &expr.span);
+                    }
+                    self.expr(expr);
+                }
+            }
+            StatementKind::While(condition, body) => {
+                self.expr(condition);
+                self.statement(body);
+            }
+        }
+    }
+
+    fn function(&mut self, decl: &FunctionDecl, kind: FunctionKind) {
+        let enclosing_function = mem::replace(&mut self.function, kind);
+        self.begin_scope();
+        for param in &decl.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_all(&decl.body);
+        self.end_scope();
+        self.function = enclosing_function;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.node {
+            ExprKind::Assign(variable, value) => {
+                self.expr(value);
+                self.local(variable);
+            }
+            ExprKind::Binary(_, left, right) | ExprKind::Logical(_, left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Call(callee, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Get(object, _) => self.expr(object),
+            ExprKind::Grouping(expr) | ExprKind::Unary(_, expr) => self.expr(expr),
+            ExprKind::Literal(_) => (),
+            ExprKind::Self_(variable) => {
+                if self.class == ClassKind::None {
+                    self.error(Code::SelfOutsideClass, "Can't use 'self' outside of a class.", &variable.span);
+                    return;
+                }
+                self.local(variable);
+            }
+            ExprKind::Set(object, _, value) => {
+                self.expr(value);
+                self.expr(object);
+            }
+            ExprKind::Super(variable, _) => {
+                let span = &variable.span;
+                match self.class {
+                    ClassKind::None => self.error(Code::SuperOutsideClass, "Can't use 'super' outside of a class.", span),
+                    ClassKind::Class => self.error(Code::SuperWithoutSuperclass, "Can't use 'super' in a class with no superclass.", span),
+                    ClassKind::Subclass => self.local(variable),
+                }
+            }
+            ExprKind::Variable(variable) => self.variable(variable),
+        }
+    }
+
+    fn variable(&mut self, variable: &Variable) {
+        let declared_only = self
+            .scopes
+            .last()
+            .and_then(|scope| scope.get(&variable.name))
+            .is_some_and(|local| !local.defined);
+
+        if declared_only {
+            let message = format!("Can't read local variable '{}' in its own initializer.", variable.name);
+            self.error(Code::ReadInOwnInitializer, &message, &variable.span);
+        }
+        self.local(variable);
+    }
+
+    /// Records the slot of `variable` if it's a local. Otherwise it's
+    /// left unresolved and looked up as a global at runtime.
+    fn local(&mut self, variable: &Variable) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(local) = scope.get(&variable.name) {
+                variable.slot.set(Some(Slot { depth, index: local.index }));
+                return;
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Spanned<Identifier>) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.contains_key(&name.node) {
+            let message = format!("Already a variable named '{name}' in this scope.");
+            self.error(Code::AlreadyDeclared, &message, &name.span);
+            return;
+        }
+
+        let index = scope.len();
+        scope.insert(name.node, Local { index, defined: false });
+    }
+
+    fn define(&mut self, name: &Spanned<Identifier>) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        let index = scope.len();
+        scope
+            .entry(name.node)
+            .or_insert(Local { index, defined: false })
+            .defined = true;
+    }
+
+    fn error(&mut self, code: Code, message: &str, span: &Span) {
+        self.errors.push(ResolverError {
+            code,
+            message: message.to_string(),
+            span: span.clone(),
+        })
+    }
+}
+
+#[test]
+fn reports_scope_errors() {
+    use crate::{parser::Parser, scanner::Scanner};
+
+    let source = "
+        return;
+        print self;
+        { let a = a; }
+        fn f() { let b = 1; let b = 2; }
+    ";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    let errors = Resolver::default().resolve(&program).unwrap_err();
+
+    let found: Vec<_> = errors
+        .iter()
+        .map(|err| (err.code, err.span.offset(), &source[err.span.offset()..err.span.offset() + err.span.len()]))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Code::TopLevelReturn, source.find("return;").unwrap(), "return;"),
+            (Code::SelfOutsideClass, source.find("self;").unwrap(), "self"),
+            (Code::ReadInOwnInitializer, source.find("a; }").unwrap(), "a"),
+            (Code::AlreadyDeclared, source.find("b = 2").unwrap(), "b"),
+        ]
+    );
+}
@@ -1,5 +1,5 @@
 use crate::{
-    error::ScannerError, token::{Span, Token}, token_kind::TokenKind, LoxNumber
+    code::Code, error::ScannerError, symbol::Symbol, token::{Span, Token}, token_kind::TokenKind, LoxNumber
 };
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
@@ -10,7 +10,6 @@ pub struct Scanner {
     pub tokens: Vec<Token>,
     start_of_lexeme: usize,
     current: usize,
-    line: usize,
     errors: Vec<ScannerError>,
 }
 
@@ -18,7 +17,6 @@ impl Scanner {
     pub fn new(source: &str) -> Self {
         Self {
             source: source.to_string(),
-            line: 1,
             ..Default::default()
         }
     }
@@ -91,30 +89,26 @@ impl Scanner {
                     self.add_token(Slash);
                 }
             }
-            ' ' | '\r' | '\t' => (), // Ignore  whitespace.
-            '\n' => self.line += 1,
+            ' ' | '\r' | '\t' | '\n' => (), // Ignore  whitespace.
 
             // Literals:
             '"' => self.literal_string(),
             '0'..='9' => self.literal_number(),
 
             // Identifiers:
-            'a'..='z' | 'A'..='Z' => self.identifier(),
+            c if is_alpha(c) => self.identifier(),
 
-            _ => self.push_error("Unexpected character"),
+            _ => self.push_error(Code::UnexpectedCharacter, "Unexpected character"),
         };
     }
 
     fn literal_string(&mut self) {
         while self.peek() != '"' && !self.eof() {
-            if self.peek() == '\n' {
-                self.line += 1
-            }
             self.advance();
         }
 
         if self.eof() {
-            self.push_error("Unterminated string.");
+            self.push_error(Code::UnterminatedString, "Unterminated string.");
             return;
         }
 
@@ -126,17 +120,17 @@ impl Scanner {
     }
 
     fn literal_number(&mut self) {
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
         // Look for a fractional part.
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             // Consume the '.'
             self.advance();
 
             // Consume the decimal part.
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
@@ -151,7 +145,7 @@ impl Scanner {
     }
 
     fn identifier(&mut self) {
-        while is_alpha(self.peek()) {
+        while is_alpha(self.peek()) || self.peek().is_ascii_digit() {
             self.advance();
         }
 
@@ -159,14 +153,14 @@ impl Scanner {
         if let Some(token) = KEYWORDS.get(value.as_str()) {
             self.add_token(token.clone());
         } else {
-            self.add_token(TokenKind::Identifier(value));
+            self.add_token(TokenKind::Identifier(Symbol::intern(&value)));
         }
     }
 
     fn advance(&mut self) -> char {
         let c = self.current();
         self.current += 1;
-        c as char
+        c
     }
 
     /// Returns `true` if the next character matches the `expected` character.
@@ -210,9 +204,11 @@ impl Scanner {
         self.current >= self.source.len()
     }
 
-    fn push_error(&mut self, message: &str) {
+    /// Reports an error spanning the lexeme scanned so far.
+    fn push_error(&mut self, code: Code, message: &str) {
         self.errors.push(ScannerError {
-            line: self.line,
+            code,
+            span: Span::new(self.start_of_lexeme, self.current - self.start_of_lexeme),
             message: message.to_string(),
         })
     }
@@ -222,7 +218,7 @@ fn is_alpha(c: char) -> bool {
     matches!(c, 'a'..='z' | 'A'..='Z' | '_')
 }
 
-const KEYWORDS: Lazy<HashMap<&str, TokenKind>> = Lazy::new(|| {
+static KEYWORDS: Lazy<HashMap<&str, TokenKind>> = Lazy::new(|| {
     HashMap::from([
         ("and", TokenKind::And),
         ("class", TokenKind::Class),
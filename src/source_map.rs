@@ -0,0 +1,82 @@
+use std::fmt::Display;
+
+use crate::token::Span;
+
+/// Converts the byte offsets stored in a [`Span`] into line and column
+/// numbers, so diagnostics can point at `file:line:col`.
+pub struct SourceMap {
+    path: String,
+    source: String,
+    /// Offset of the first byte of every line.
+    line_starts: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(path: &str, source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            path: path.to_string(),
+            source: source.to_string(),
+            line_starts,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the text of the 1-based `line`, without its line break.
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |next| next - 1);
+        self.source[start..end].trim_end_matches('\r')
+    }
+
+    /// Returns the 1-based line and column where `span` starts.
+    pub fn location(&self, span: &Span) -> Location<'_> {
+        let offset = span.offset();
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let column = offset - self.line_starts[line - 1] + 1;
+
+        Location {
+            path: &self.path,
+            line,
+            column,
+        }
+    }
+}
+
+pub struct Location<'a> {
+    pub path: &'a str,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Location<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.column)
+    }
+}
+
+#[test]
+fn offsets_map_to_lines_and_columns() {
+    let map = SourceMap::new("test.lox", "let a = 1;\nprint a;\n");
+
+    let at = |offset| {
+        let location = map.location(&Span::new(offset, 1));
+        (location.line, location.column)
+    };
+    assert_eq!(at(0), (1, 1));
+    assert_eq!(at(4), (1, 5));
+    assert_eq!(at(10), (1, 11));
+    assert_eq!(at(11), (2, 1));
+    assert_eq!(at(17), (2, 7));
+    assert_eq!(map.line(2), "print a;");
+    assert_eq!(map.line(3), "");
+}
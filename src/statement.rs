@@ -1,11 +1,32 @@
 pub mod eval;
 
-use crate::{expr::Expr, Identifier};
+use std::rc::Rc;
 
+use crate::{expr::{Expr, Variable}, token::Spanned, Identifier};
 
-pub enum Statement {
+pub type Statement = Spanned<StatementKind>;
+
+pub enum StatementKind {
     Block(Vec<Statement>),
+    Class(ClassDecl),
     Expr(Expr),
+    Fn(Rc<FunctionDecl>),
+    If(Expr, Box<Statement>, Option<Box<Statement>>),
     Print(Expr),
-    Let(Identifier, Expr),
+    Return(Option<Expr>),
+    /// `let x;` has no initializer and binds `nil`.
+    Let(Spanned<Identifier>, Option<Expr>),
+    While(Expr, Box<Statement>),
+}
+
+pub struct FunctionDecl {
+    pub name: Spanned<Identifier>,
+    pub params: Vec<Spanned<Identifier>>,
+    pub body: Vec<Statement>,
+}
+
+pub struct ClassDecl {
+    pub name: Spanned<Identifier>,
+    pub superclass: Option<Variable>,
+    pub methods: Vec<Rc<FunctionDecl>>,
 }
@@ -1,20 +1,190 @@
-use crate::{environment::Environment, error::RuntimeError};
-use super::Statement;
+use std::{ops::ControlFlow, rc::Rc};
 
+use crate::{
+    class::Class, environment::Environment, error::{InvalidSuperclass, RuntimeError},
+    expr::eval::Value, function::Function, gc,
+};
+use super::{Statement, StatementKind};
 
+/// The outcome of running a statement: either fall through to the next
+/// one, or unwind to the enclosing call with the returned value.
+pub type Flow = ControlFlow<Value>;
 
 impl Statement {
-    pub fn eval(self, env: &mut Environment) -> Result<(), RuntimeError> {
-        match self {
-            Statement::Block(stmts) => {
-                let mut new_env = env.push();
-                for stmt in stmts { stmt.eval(&mut new_env)? }
-            }
-            Statement::Expr(expr) => { expr.eval(env)?; },
-            Statement::Let(name, expr) => env.bind(name, expr.eval(env)?),
-            Statement::Print(expr) => println!("{}", expr.eval(env)?),
+    pub fn eval(&self, env: &Environment) -> Result<Flow, RuntimeError> {
+        match &self.node {
+            StatementKind::Block(stmts) => return eval_block(stmts, &env.push()),
+            StatementKind::Class(decl) => {
+                let superclass = match &decl.superclass {
+                    Some(variable) => match env.get(variable)? {
+                        Value::Class(class) => Some(class),
+                        _ => {
+                            let (name, span) = (variable.name, variable.span.clone());
+                            return Err(InvalidSuperclass { name, span }.into());
+                        }
+                    },
+                    None => None,
+                };
+
+                // Methods of a subclass close over a scope where `super` is bound.
+                let method_env = match &superclass {
+                    Some(superclass) => {
+                        let method_env = env.push();
+                        method_env.bind("super".into(), Value::Class(Rc::clone(superclass)));
+                        method_env
+                    }
+                    None => env.clone(),
+                };
+
+                let methods = decl
+                    .methods
+                    .iter()
+                    .map(|method| {
+                        let function = Function::method(Rc::clone(method), method_env.clone());
+                        (method.name.node, gc::alloc(function))
+                    })
+                    .collect();
+                let class = Class::new(decl.name.node, superclass, methods);
+                env.bind(decl.name.node, Value::Class(gc::alloc(class)))
+            }
+            StatementKind::Expr(expr) => { expr.eval(env)?; },
+            StatementKind::Fn(decl) => {
+                let function = Function::new(Rc::clone(decl), env.clone());
+                env.bind(decl.name.node, Value::Function(gc::alloc(function)))
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                if condition.eval(env)?.is_truthy() {
+                    return then_branch.eval(env);
+                } else if let Some(else_branch) = else_branch {
+                    return else_branch.eval(env);
+                }
+            }
+            StatementKind::Let(name, expr) => {
+                let value = match expr {
+                    Some(expr) => expr.eval(env)?,
+                    None => Value::Nil,
+                };
+                env.bind(name.node, value)
+            }
+            StatementKind::Print(expr) => println!("{}", expr.eval(env)?),
+            StatementKind::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => expr.eval(env)?,
+                    None => Value::Nil,
+                };
+                return Ok(ControlFlow::Break(value));
+            }
+            StatementKind::While(condition, body) => {
+                while condition.eval(env)?.is_truthy() {
+                    if let flow @ ControlFlow::Break(_) = body.eval(env)? {
+                        return Ok(flow);
+                    }
+                }
+            }
         };
 
-        Ok(())
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Runs `stmts` in the current scope, stopping early on `return`.
+pub fn eval_block(stmts: &[Statement], env: &Environment) -> Result<Flow, RuntimeError> {
+    for stmt in stmts {
+        if let flow @ ControlFlow::Break(_) = stmt.eval(env)? {
+            return Ok(flow);
+        }
     }
-}
\ No newline at end of file
+    Ok(ControlFlow::Continue(()))
+}
+
+/// Resolves and runs `source` in a fresh environment, which is returned so
+/// tests can look at the globals the program left behind.
+#[cfg(test)]
+pub fn run_source(source: &str) -> (Environment, Result<Flow, RuntimeError>) {
+    use crate::{parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let Ok(program) = Parser::new(tokens).parse() else {
+        panic!("expected a program");
+    };
+    Resolver::default().resolve(&program).unwrap();
+    let env = Environment::default();
+    let result = eval_block(&program, &env);
+    (env, result)
+}
+
+#[test]
+fn if_picks_a_branch_by_truthiness() {
+    let (env, result) = run_source("
+        let zero = \"falsy\";
+        if (0) zero = \"truthy\";
+        let empty = \"falsy\";
+        if (\"\") empty = \"truthy\";
+        let none = \"truthy\";
+        if (nil) none = \"truthy\"; else none = \"falsy\";
+        let no = \"truthy\";
+        if (false) no = \"truthy\"; else no = \"falsy\";
+        let dangling = \"none\";
+        if (true) if (false) dangling = \"inner\"; else dangling = \"inner else\";
+    ");
+
+    assert!(result.unwrap().is_continue());
+    assert_eq!(env.global("zero").to_string(), "truthy");
+    assert_eq!(env.global("empty").to_string(), "truthy");
+    assert_eq!(env.global("none").to_string(), "falsy");
+    assert_eq!(env.global("no").to_string(), "falsy");
+    // An `else` belongs to the nearest `if`.
+    assert_eq!(env.global("dangling").to_string(), "inner else");
+}
+
+#[test]
+fn while_reevaluates_its_condition() {
+    let (env, result) = run_source("
+        let i = 0;
+        let sum = 0;
+        while (i < 5) {
+            sum = sum + i;
+            i = i + 1;
+        }
+        let ran = false;
+        while (nil) ran = true;
+        fn firstSquareAbove(n) {
+            let i = 0;
+            while (true) {
+                if (i * i > n) return i;
+                i = i + 1;
+            }
+        }
+        let root = firstSquareAbove(10);
+    ");
+
+    assert!(result.unwrap().is_continue());
+    assert_eq!(env.global("sum").to_string(), "10");
+    assert_eq!(env.global("i").to_string(), "5");
+    assert_eq!(env.global("ran").to_string(), "false");
+    assert_eq!(env.global("root").to_string(), "4");
+}
+
+#[test]
+fn for_loops_scope_their_variable() {
+    let (env, result) = run_source("
+        let i = \"outer\";
+        let sum = 0;
+        for (let i = 0; i < 4; i = i + 1) sum = sum + i;
+        let j = 10;
+        for (j = 0; j < 3; j = j + 1) {}
+        fn countdown(n) {
+            for (;;) {
+                if (n < 1) return \"liftoff\";
+                n = n - 1;
+            }
+        }
+        let launched = countdown(3);
+    ");
+
+    assert!(result.unwrap().is_continue());
+    assert_eq!(env.global("i").to_string(), "outer");
+    assert_eq!(env.global("sum").to_string(), "6");
+    assert_eq!(env.global("j").to_string(), "3");
+    assert_eq!(env.global("launched").to_string(), "liftoff");
+}
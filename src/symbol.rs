@@ -0,0 +1,82 @@
+use std::{cell::RefCell, collections::HashMap, fmt::{Debug, Display}, rc::Rc};
+
+thread_local! {
+    static SYMBOLS: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+/// An interned name. Names are interned once, when they are scanned, so
+/// scopes, fields and methods are keyed on a number that is cheap to copy,
+/// hash and compare, instead of on the name's text.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+impl Symbol {
+    /// Returns the symbol for `name`, creating it the first time `name` is seen.
+    pub fn intern(name: &str) -> Self {
+        SYMBOLS.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&symbol) = interner.symbols.get(name) {
+                return symbol;
+            }
+
+            let symbol = Symbol(interner.names.len() as u32);
+            let name: Rc<str> = name.into();
+            interner.names.push(Rc::clone(&name));
+            interner.symbols.insert(name, symbol);
+            symbol
+        })
+    }
+
+    /// The name this symbol was interned from.
+    pub fn as_str(&self) -> Rc<str> {
+        SYMBOLS.with(|interner| Rc::clone(&interner.borrow().names[self.0 as usize]))
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        *self.as_str() == *other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[test]
+fn interning_the_same_name_gives_the_same_symbol() {
+    let a = Symbol::intern("counter");
+    let b = Symbol::intern(&String::from("counter"));
+    let c = Symbol::intern("count");
+
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(a, "counter");
+    assert_eq!(c.to_string(), "count");
+}
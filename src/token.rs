@@ -1,3 +1,5 @@
+use std::{fmt::Display, ops::Deref};
+
 use crate::token_kind::TokenKind;
 
 #[derive(Debug, Clone)]
@@ -23,7 +25,7 @@ impl std::fmt::Display for Token {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Span {
     offset: usize,
     length: usize,
@@ -33,6 +35,25 @@ impl Span {
     pub fn new(offset: usize, length: usize) -> Self {
         Self { offset, length }
     }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Returns the smallest span covering both `self` and `other`.
+    pub fn merge(&self, other: &Span) -> Span {
+        let start = self.offset.min(other.offset);
+        let end = (self.offset + self.length).max(other.offset + other.length);
+        Span::new(start, end - start)
+    }
 }
 
 impl std::fmt::Display for Span {
@@ -40,3 +61,31 @@ impl std::fmt::Display for Span {
         write!(f, "[offset: {}; length: {}]", self.offset, self.length)
     }
 }
+
+/// A syntax node together with the span of source it was parsed from.
+/// It derefs to the node, so matching on it reads like matching the node.
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Self { node, span }
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.node
+    }
+}
+
+impl<T: Display> Display for Spanned<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.node.fmt(f)
+    }
+}
@@ -0,0 +1,635 @@
+pub mod value;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    rc::Rc,
+};
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+    disassembler::Disassembler,
+    error::{
+        ArityError, Frame, InvalidSuperclass, NotAnInstance, NotCallable, RuntimeError, StackOverflow,
+        TypeError, UnboundVariable, UndefinedProperty,
+    },
+    expr::{eval::MAX_CALL_DEPTH, BinaryOp, UnaryOp},
+    gc,
+    native::natives,
+    source_map::SourceMap,
+    token::Span,
+    Identifier,
+};
+
+use value::{BoundMethod, Class, Closure, Instance, Upvalue, Value};
+
+/// The script's own frame and [`MAX_CALL_DEPTH`] calls, so both backends
+/// overflow at the same depth.
+const FRAMES_MAX: usize = MAX_CALL_DEPTH + 1;
+
+/// A stack machine that runs the bytecode made by the
+/// [`Compiler`](crate::compiler::Compiler). Globals persist between
+/// calls to [`Vm::run`], so the prompt can use one VM for every line.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Identifier, Value>,
+    /// Upvalues still pointing into the stack, so closures created in the
+    /// same scope share them.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    /// The frames that were active when the last runtime error was raised.
+    backtrace: Vec<Frame>,
+    /// The name of initializers, interned once since every class call looks it up.
+    init: Identifier,
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Where slot zero of the frame is on the stack.
+    base: usize,
+    /// The name shown in backtraces: the function's, or the class's for an initializer.
+    function: Identifier,
+    call_site: Span,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        let globals = natives()
+            .into_iter()
+            .map(|native| (native.name.into(), Value::NativeFunction(native)))
+            .collect();
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            open_upvalues: Vec::new(),
+            backtrace: Vec::new(),
+            init: "init".into(),
+        }
+    }
+}
+
+impl Vm {
+    /// Runs a script function made by the compiler.
+    pub fn run(&mut self, script: Rc<FunctionProto>) -> Result<(), RuntimeError> {
+        self.start(script, None)
+    }
+
+    /// Runs a script function like [`Vm::run`], printing the stack and the
+    /// next instruction to stderr before each instruction executes.
+    pub fn run_traced(&mut self, script: Rc<FunctionProto>, source_map: &SourceMap) -> Result<(), RuntimeError> {
+        self.start(script, Some(&Disassembler::new(source_map)))
+    }
+
+    fn start(&mut self, script: Rc<FunctionProto>, tracer: Option<&Disassembler>) -> Result<(), RuntimeError> {
+        let closure = gc::alloc(Closure { proto: script, upvalues: Vec::new() });
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: 0,
+            function: "script".into(),
+            call_site: Span::default(),
+        });
+
+        let result = self.execute(tracer);
+        if result.is_err() {
+            // The script's own frame isn't a call.
+            self.backtrace = self.frames[1..]
+                .iter()
+                .map(|frame| Frame { function: frame.function, call_site: frame.call_site.clone() })
+                .collect();
+        }
+
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        result
+    }
+
+    /// Takes the frames that were active when the last runtime error was
+    /// raised, innermost last.
+    pub fn take_backtrace(&mut self) -> Vec<Frame> {
+        mem::take(&mut self.backtrace)
+    }
+
+    /// Reads the global `name`, for tests.
+    #[cfg(test)]
+    pub fn global(&self, name: &str) -> Value {
+        self.globals.get(&name.into()).cloned().unwrap_or_else(|| panic!("`{name}` is not defined"))
+    }
+
+    fn execute(&mut self, tracer: Option<&Disassembler>) -> Result<(), RuntimeError> {
+        loop {
+            let start = self.frame().ip;
+            if let Some(tracer) = tracer {
+                let stack: String = self.stack.iter().map(|value| format!("[ {value} ]")).collect();
+                eprintln!("          {stack}");
+                eprintln!("{}", tracer.instruction(self.chunk(), start).0);
+            }
+            let op = OpCode::try_from(self.read_byte()).expect("the compiler only emits valid opcodes");
+
+            match op {
+                OpCode::Constant => {
+                    let value = match self.read_constant() {
+                        Constant::Number(n) => Value::Number(*n),
+                        Constant::String(s) => Value::String(Rc::clone(s)),
+                        Constant::Name(_) | Constant::Function(_) => {
+                            unreachable!("names are operands, and functions are loaded with `Closure`")
+                        }
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(UnboundVariable(name, self.span(start)).into()),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(UnboundVariable(name, self.span(start)).into()),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let value = match &*self.frame().closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let value = self.peek(0).clone();
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    };
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+                    let Value::Instance(instance) = self.pop() else {
+                        return Err(NotAnInstance { span: self.span(start) }.into());
+                    };
+                    let field = instance.fields.borrow().get(&name).cloned();
+                    let value = match field {
+                        Some(value) => value,
+                        None => {
+                            let method = instance.class.methods.borrow().get(&name).cloned();
+                            match method {
+                                Some(method) => bind(Value::Instance(instance), method),
+                                None => return Err(UndefinedProperty(name, self.span(start)).into()),
+                            }
+                        }
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    let Value::Instance(instance) = self.pop() else {
+                        return Err(NotAnInstance { span: self.span(start) }.into());
+                    };
+                    instance.fields.borrow_mut().insert(name, value.clone());
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_name();
+                    // The compiler only emits this for `super`, which is always a
+                    // class, but a `.loxc` file could have been written by hand.
+                    let superclass = match self.pop() {
+                        Value::Class(superclass) => superclass,
+                        other => {
+                            let operands = vec![other.type_name()];
+                            return Err(TypeError { operator: "super".into(), operands, span: self.span(start) }.into());
+                        }
+                    };
+                    let receiver = self.pop();
+                    let method = superclass.methods.borrow().get(&name).cloned();
+                    match method {
+                        Some(method) => self.stack.push(bind(receiver, method)),
+                        None => return Err(UndefinedProperty(name, self.span(start)).into()),
+                    }
+                }
+                OpCode::Equal => {
+                    let (y, x) = (self.pop(), self.pop());
+                    self.stack.push(Value::Boolean(x == y));
+                }
+                OpCode::NotEqual => {
+                    let (y, x) = (self.pop(), self.pop());
+                    self.stack.push(Value::Boolean(x != y));
+                }
+                OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide => self.binary(op, start)?,
+                OpCode::Not | OpCode::Negate => self.unary(op, start)?,
+                OpCode::Print => println!("{}", self.pop()),
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let args = self.read_byte() as usize;
+                    self.call_value(args, self.span(start))?;
+                }
+                OpCode::Closure => {
+                    let Constant::Function(proto) = self.read_constant().clone() else {
+                        unreachable!("`Closure` always refers to a function");
+                    };
+                    let upvalues = (0..proto.upvalue_count)
+                        .map(|_| {
+                            let is_local = self.read_byte() == 1;
+                            let index = self.read_byte() as usize;
+                            if is_local {
+                                self.capture_upvalue(self.frame().base + index)
+                            } else {
+                                Rc::clone(&self.frame().closure.upvalues[index])
+                            }
+                        })
+                        .collect();
+                    self.stack.push(Value::Closure(gc::alloc(Closure { proto, upvalues })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("a frame is running");
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_name();
+                    let class = Class { name, methods: Default::default() };
+                    self.stack.push(Value::Class(gc::alloc(class)));
+                }
+                OpCode::Subclass => {
+                    let name = self.read_name();
+                    let superclass_name = self.read_name();
+                    let Value::Class(superclass) = self.pop() else {
+                        let (name, span) = (superclass_name, self.span(start));
+                        return Err(InvalidSuperclass { name, span }.into());
+                    };
+                    let methods = superclass.methods.clone();
+                    self.stack.push(Value::Class(gc::alloc(Class { name, methods })));
+                    self.stack.push(Value::Class(superclass));
+                }
+                OpCode::Method => {
+                    let name = self.read_name();
+                    // Like `super`, only a hand-written `.loxc` file gets these wrong.
+                    let method = self.pop();
+                    let (Value::Class(class), Value::Closure(method)) = (self.peek(0), &method) else {
+                        let operands = vec![self.peek(0).type_name(), method.type_name()];
+                        return Err(TypeError { operator: "method".into(), operands, span: self.span(start) }.into());
+                    };
+                    class.methods.borrow_mut().insert(name, Rc::clone(method));
+                }
+            }
+        }
+    }
+
+    fn binary(&mut self, op: OpCode, start: usize) -> Result<(), RuntimeError> {
+        use Value::*;
+        let (y, x) = (self.pop(), self.pop());
+        let value = match (op, &x, &y) {
+            (OpCode::Subtract, Number(x), Number(y)) => Number(x - y),
+            (OpCode::Add, Number(x), Number(y)) => Number(x + y),
+            (OpCode::Add, String(x), String(y)) => String(format!("{x}{y}").into()),
+            (OpCode::Divide, Number(x), Number(y)) => Number(x / y),
+            (OpCode::Multiply, Number(x), Number(y)) => Number(x * y),
+            (OpCode::Less, Number(x), Number(y)) => Boolean(x < y),
+            (OpCode::Greater, Number(x), Number(y)) => Boolean(x > y),
+            (OpCode::LessEqual, Number(x), Number(y)) => Boolean(x <= y),
+            (OpCode::GreaterEqual, Number(x), Number(y)) => Boolean(x >= y),
+            _ => {
+                // Name the operator like the tree-walker does.
+                let operator = match op {
+                    OpCode::Subtract => BinaryOp::Minus,
+                    OpCode::Add => BinaryOp::Plus,
+                    OpCode::Divide => BinaryOp::Div,
+                    OpCode::Multiply => BinaryOp::Mul,
+                    OpCode::Less => BinaryOp::Less,
+                    OpCode::Greater => BinaryOp::Greater,
+                    OpCode::LessEqual => BinaryOp::LessEqual,
+                    _ => BinaryOp::GreaterEqual,
+                };
+                let operands = vec![x.type_name(), y.type_name()];
+                return Err(TypeError { operator: operator.to_string(), operands, span: self.span(start) }.into());
+            }
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn unary(&mut self, op: OpCode, start: usize) -> Result<(), RuntimeError> {
+        let value = match (op, self.pop()) {
+            (OpCode::Negate, Value::Number(n)) => Value::Number(-n),
+            (OpCode::Not, Value::Boolean(b)) => Value::Boolean(!b),
+            (op, x) => {
+                let operator = if op == OpCode::Negate { UnaryOp::Minus } else { UnaryOp::Not };
+                let operands = vec![x.type_name()];
+                return Err(TypeError { operator: operator.to_string(), operands, span: self.span(start) }.into());
+            }
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Calls the value below the top `args` values of the stack.
+    fn call_value(&mut self, args: usize, span: Span) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - args - 1;
+        match self.stack[base].clone() {
+            Value::Closure(closure) => {
+                let function = closure.proto.name;
+                self.call(closure, function, args, span)
+            }
+            Value::BoundMethod(bound) => {
+                self.stack[base] = bound.receiver.clone();
+                let function = bound.method.proto.name;
+                self.call(Rc::clone(&bound.method), function, args, span)
+            }
+            Value::Class(class) => {
+                let instance = Instance { class: Rc::clone(&class), fields: Default::default() };
+                self.stack[base] = Value::Instance(gc::alloc(instance));
+                let init = class.methods.borrow().get(&self.init).cloned();
+                match init {
+                    Some(init) => self.call(init, class.name, args, span),
+                    None if args != 0 => Err(ArityError { expected: 0, found: args, span }.into()),
+                    None => Ok(()),
+                }
+            }
+            Value::NativeFunction(native) => {
+                if args != native.arity {
+                    return Err(ArityError { expected: native.arity, found: args, span }.into());
+                }
+                let result = (native.fun)(&self.stack[base + 1..]);
+                self.stack.truncate(base);
+                self.stack.push(result);
+                Ok(())
+            }
+            _ => Err(NotCallable { span }.into()),
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, function: Identifier, args: usize, span: Span) -> Result<(), RuntimeError> {
+        if args != closure.proto.arity {
+            return Err(ArityError { expected: closure.proto.arity, found: args, span }.into());
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err(StackOverflow { span }.into());
+        }
+        let base = self.stack.len() - args - 1;
+        self.frames.push(CallFrame { closure, ip: 0, base, function, call_site: span });
+        Ok(())
+    }
+
+    /// Returns the upvalue for the stack slot `slot`, reusing an open one if
+    /// another closure already captured it.
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+
+        let upvalue = gc::alloc(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Moves the values of every slot from `from` upwards into the upvalues that captured them.
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("a frame is running")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("a frame is running")
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self.frame().closure.proto.chunk
+    }
+
+    /// The source of the instruction that starts at `offset` in the running chunk.
+    fn span(&self, offset: usize) -> Span {
+        self.chunk().spans[offset].clone()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        frame.ip += 1;
+        frame.closure.proto.chunk.code[frame.ip - 1]
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        frame.ip += 2;
+        frame.closure.proto.chunk.read_u16(frame.ip - 2)
+    }
+
+    fn read_constant(&mut self) -> &Constant {
+        let index = self.read_u16() as usize;
+        &self.chunk().constants[index]
+    }
+
+    fn read_name(&mut self) -> Identifier {
+        match self.read_constant() {
+            Constant::Name(name) => *name,
+            _ => unreachable!("names are string constants"),
+        }
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler keeps the stack balanced")
+    }
+}
+
+fn bind(receiver: Value, method: Rc<Closure>) -> Value {
+    Value::BoundMethod(gc::alloc(BoundMethod { receiver, method }))
+}
+
+/// Compiles and runs `source` on a fresh VM, stopping at the first runtime
+/// error like the tree-walker's `run_source` does.
+#[cfg(test)]
+pub fn run_source(source: &str) -> (Vm, Result<(), RuntimeError>) {
+    use crate::{compiler::Compiler, parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Resolver::default().resolve(&program).unwrap();
+    let Ok(scripts) = Compiler::default().compile(&program) else {
+        panic!("expected the program to compile");
+    };
+    let mut vm = Vm::default();
+    let result = scripts.into_iter().try_for_each(|script| vm.run(script));
+    (vm, result)
+}
+
+#[test]
+fn runs_closures_and_classes() {
+    use crate::{compiler::Compiler, parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    let source = "
+        fn makeCounter() {
+            let i = 0;
+            fn count() { i = i + 1; return i; }
+            return count;
+        }
+        let counter = makeCounter();
+        counter();
+        let n = counter();
+
+        class A { init(x) { self.x = x; } get() { return self.x; } }
+        class B < A { get() { return super.get() * 10; } }
+        let b = B(4).get();
+    ";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Resolver::default().resolve(&program).unwrap();
+    let Ok(scripts) = Compiler::default().compile(&program) else {
+        panic!("expected the program to compile");
+    };
+
+    let mut vm = Vm::default();
+    for script in scripts {
+        assert!(vm.run(script).is_ok());
+    }
+
+    assert!(matches!(vm.globals[&"n".into()], Value::Number(n) if n == 2.));
+    assert!(matches!(vm.globals[&"b".into()], Value::Number(b) if b == 40.));
+}
+
+#[test]
+fn runaway_recursion_overflows_in_both_backends() {
+    use std::thread;
+
+    use crate::{
+        code::Code, compiler::Compiler, expr::eval::{self, STACK_SIZE}, parser::Parser, resolver::Resolver,
+        scanner::Scanner, statement::eval::run_source,
+    };
+
+    let source = "fn f(n) { return f(n + 1); } f(0);";
+    let call = source.find("f(n + 1)").unwrap();
+
+    // The test harness's threads are too small for the tree-walker's deepest calls.
+    let tree = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        let Err(err) = run_source(source).1 else {
+            panic!("expected a stack overflow");
+        };
+        assert_eq!(err.code(), Code::StackOverflow);
+        assert_eq!(err.span().offset(), call);
+        assert_eq!(eval::take_backtrace().len(), MAX_CALL_DEPTH);
+    });
+    tree.unwrap().join().unwrap();
+
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Resolver::default().resolve(&program).unwrap();
+    let Ok(scripts) = Compiler::default().compile(&program) else {
+        panic!("expected the program to compile");
+    };
+    let mut vm = Vm::default();
+    let results: Vec<_> = scripts.into_iter().map(|script| vm.run(script)).collect();
+    let Some(Err(err)) = results.last() else {
+        panic!("expected a stack overflow");
+    };
+    assert_eq!(err.code(), Code::StackOverflow);
+    assert_eq!(err.span().offset(), call);
+    assert_eq!(vm.take_backtrace().len(), MAX_CALL_DEPTH);
+}
+
+#[test]
+fn setting_a_property_evaluates_the_value_first_in_both_backends() {
+    use crate::{code::Code, expr, statement::eval};
+
+    let source = "
+        let calls = 0;
+        fn f() { calls = calls + 1; return 1; }
+        let n = nil;
+        n.x = f();
+    ";
+    let name = source.find("x =").unwrap();
+
+    let (env, result) = eval::run_source(source);
+    let Err(err) = result else {
+        panic!("expected setting a field on nil to fail");
+    };
+    assert_eq!(err.code(), Code::NotAnInstance);
+    assert_eq!(err.span().offset(), name);
+    assert!(matches!(env.global("calls"), expr::eval::Value::Number(n) if n == 1.));
+
+    let (vm, result) = run_source(source);
+    let Err(err) = result else {
+        panic!("expected setting a field on nil to fail");
+    };
+    assert_eq!(err.code(), Code::NotAnInstance);
+    assert_eq!(err.span().offset(), name);
+    assert!(matches!(vm.global("calls"), Value::Number(n) if n == 1.));
+}
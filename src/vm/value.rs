@@ -0,0 +1,177 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    chunk::FunctionProto,
+    gc::{self, Trace, Tracer},
+    native::{NativeFunction, NativeValue},
+    Identifier, LoxNumber,
+};
+
+/// A value on the stack of the [`Vm`](super::Vm). It prints and compares
+/// exactly like the tree-walker's [`Value`](crate::expr::eval::Value).
+#[derive(Clone)]
+pub enum Value {
+    Boolean(bool),
+    Number(LoxNumber),
+    String(Rc<str>),
+    Closure(Rc<Closure>),
+    BoundMethod(Rc<BoundMethod>),
+    NativeFunction(NativeFunction<Value>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    Nil,
+}
+
+impl Value {
+    /// Lox truthiness: `nil` and `false` are falsy, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    /// Reports the heap object this value refers to, if it refers to one.
+    pub fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Value::Closure(closure) => tracer.edge(closure),
+            Value::BoundMethod(bound) => tracer.edge(bound),
+            Value::Class(class) => tracer.edge(class),
+            Value::Instance(instance) => tracer.edge(instance),
+            _ => (),
+        }
+    }
+
+    /// The name of the value's dynamic type, as shown in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Boolean(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Closure(_) | Value::BoundMethod(_) | Value::NativeFunction(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Nil => "nil",
+        }
+    }
+}
+
+/// A function together with the variables it captured.
+pub struct Closure {
+    pub proto: Rc<FunctionProto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A captured variable. It stays on the stack while the function that
+/// declared it runs, and moves into the upvalue when it goes out of scope.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+pub struct Class {
+    pub name: Identifier,
+    /// Includes the inherited methods, copied in when the class is created.
+    pub methods: RefCell<HashMap<Identifier, Rc<Closure>>>,
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<HashMap<Identifier, Value>>,
+}
+
+impl NativeValue for Value {
+    fn number(n: f64) -> Self {
+        Value::Number(n)
+    }
+
+    fn record(class: &str, fields: &[(&str, f64)]) -> Self {
+        let class = gc::alloc(Class { name: class.into(), methods: Default::default() });
+        let fields = fields.iter().map(|&(name, n)| (name.into(), Value::Number(n))).collect();
+        Value::Instance(gc::alloc(Instance { class, fields: RefCell::new(fields) }))
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.upvalues.iter().for_each(|upvalue| tracer.edge(upvalue));
+    }
+}
+
+impl Trace for RefCell<Upvalue> {
+    fn trace(&self, tracer: &mut Tracer) {
+        // An open upvalue's variable is on the stack, which is a root.
+        if let Upvalue::Closed(value) = &*self.try_borrow().expect(gc::BORROWED) {
+            value.trace(tracer);
+        }
+    }
+
+    fn clear(&self) {
+        *self.try_borrow_mut().expect(gc::BORROWED) = Upvalue::Closed(Value::Nil);
+    }
+}
+
+impl Trace for BoundMethod {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.receiver.trace(tracer);
+        tracer.edge(&self.method);
+    }
+}
+
+impl Trace for Class {
+    fn trace(&self, tracer: &mut Tracer) {
+        let methods = self.methods.try_borrow().expect(gc::BORROWED);
+        methods.values().for_each(|method| tracer.edge(method));
+    }
+
+    fn clear(&self) {
+        self.methods.try_borrow_mut().expect(gc::BORROWED).clear();
+    }
+}
+
+impl Trace for Instance {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.edge(&self.class);
+        let fields = self.fields.try_borrow().expect(gc::BORROWED);
+        fields.values().for_each(|value| value.trace(tracer));
+    }
+
+    fn clear(&self) {
+        self.fields.try_borrow_mut().expect(gc::BORROWED).clear();
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Boolean(x), Value::Boolean(y)) => x == y,
+            (Value::Number(x), Value::Number(y)) => x == y,
+            (Value::String(x), Value::String(y)) => x == y,
+            (Value::Closure(x), Value::Closure(y)) => Rc::ptr_eq(x, y),
+            (Value::BoundMethod(x), Value::BoundMethod(y)) => Rc::ptr_eq(x, y),
+            (Value::NativeFunction(x), Value::NativeFunction(y)) => x.name == y.name,
+            (Value::Class(x), Value::Class(y)) => Rc::ptr_eq(x, y),
+            (Value::Instance(x), Value::Instance(y)) => Rc::ptr_eq(x, y),
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Boolean(value) => write!(f, "{value}"),
+            Value::Number(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value}"),
+            Value::Closure(closure) => write!(f, "<fn {}>", closure.proto.name),
+            Value::BoundMethod(bound) => write!(f, "<fn {}>", bound.method.proto.name),
+            Value::NativeFunction(native) => write!(f, "{native}"),
+            Value::Class(class) => write!(f, "<class {}>", class.name),
+            Value::Instance(instance) => write!(f, "<instance {}>", instance.class.name),
+            Value::Nil => write!(f, "nil"),
+        }
+    }
+}